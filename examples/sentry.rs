@@ -13,6 +13,7 @@ use rlp_derive::{RlpDecodable, RlpEncodable};
 use secp256k1::SecretKey;
 use std::{
     collections::HashMap,
+    net::SocketAddr,
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
@@ -258,11 +259,23 @@ async fn main() {
 
     let swarm = Swarm::builder()
         .with_task_group(task_group.clone())
-        .with_listen_options(ListenOptions {
+        .with_max_peers(50)
+        .with_dial_options(DialOptions {
             discovery_tasks,
-            max_peers: 50,
-            addr: format!("0.0.0.0:{}", port).parse().unwrap(),
-            cidr: None,
+            max_outbound: 50,
+        })
+        .with_listen_options(ListenOptions {
+            max_inbound: 35,
+            listen_addrs: vec![
+                format!("0.0.0.0:{}", port)
+                    .parse::<SocketAddr>()
+                    .unwrap()
+                    .into(),
+                format!("[::]:{}", port)
+                    .parse::<SocketAddr>()
+                    .unwrap()
+                    .into(),
+            ],
         })
         .build(
             btreemap! { CapabilityId {
@@ -277,6 +290,6 @@ async fn main() {
 
     loop {
         sleep(std::time::Duration::from_secs(5)).await;
-        info!("Peers: {}.", swarm.connected_peers());
+        info!("Peers: {}.", swarm.connected_peers().len());
     }
 }
@@ -0,0 +1,312 @@
+use crate::types::PeerId;
+use anyhow::{anyhow, Context as _};
+use cidr::{Cidr, IpCidr};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    fs,
+    net::IpAddr,
+    path::PathBuf,
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::*;
+
+/// What a ban applies to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BanTarget {
+    Peer(PeerId),
+    Ip(IpAddr),
+    Subnet(IpCidr),
+}
+
+impl Display for BanTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Peer(id) => write!(f, "peer:{:x}", id),
+            Self::Ip(ip) => write!(f, "ip:{}", ip),
+            Self::Subnet(cidr) => write!(f, "subnet:{}", cidr),
+        }
+    }
+}
+
+impl FromStr for BanTarget {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, ':');
+        let kind = parts.next().unwrap_or_default();
+        let value = parts
+            .next()
+            .ok_or_else(|| anyhow!("ban target has no value"))?;
+        Ok(match kind {
+            "peer" => Self::Peer(value.parse()?),
+            "ip" => Self::Ip(value.parse()?),
+            "subnet" => Self::Subnet(value.parse()?),
+            other => return Err(anyhow!("unknown ban target kind: {}", other)),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Ban {
+    pub target: BanTarget,
+    pub reason: String,
+    /// `None` if the ban is permanent.
+    pub expires_at: Option<SystemTime>,
+}
+
+impl Ban {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        matches!(self.expires_at, Some(expires_at) if expires_at <= now)
+    }
+
+    fn matches(&self, id: Option<PeerId>, ip: Option<IpAddr>) -> bool {
+        match (&self.target, id, ip) {
+            (BanTarget::Peer(banned), Some(id), _) => *banned == id,
+            (BanTarget::Ip(banned), _, Some(ip)) => *banned == ip,
+            (BanTarget::Subnet(banned), _, Some(ip)) => banned.contains(&ip),
+            _ => false,
+        }
+    }
+}
+
+/// Set of banned peers, IP addresses and subnets, optionally persisted to a file.
+///
+/// File format is one ban per line: `<target>\t<expiry as UNIX seconds, 0 if permanent>\t<reason>`.
+#[derive(Debug, Default)]
+pub struct BanList {
+    bans: Mutex<HashMap<BanTarget, Ban>>,
+    path: Option<PathBuf>,
+    /// Held while a snapshot is taken and written, so that writers do not interleave.
+    persist_lock: Mutex<()>,
+}
+
+impl BanList {
+    /// Load ban list from `path`, which is also where subsequent changes are persisted.
+    /// Missing file is treated as an empty ban list.
+    pub fn load(path: PathBuf) -> anyhow::Result<Self> {
+        let mut bans = HashMap::new();
+
+        if path.exists() {
+            let now = SystemTime::now();
+            let data = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read ban list {}", path.display()))?;
+            for (i, line) in data.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let ban = Self::parse_line(line)
+                    .with_context(|| format!("{}:{}: invalid ban", path.display(), i + 1))?;
+                if !ban.is_expired(now) {
+                    bans.insert(ban.target, ban);
+                }
+            }
+            debug!("Loaded {} bans from {}", bans.len(), path.display());
+        }
+
+        Ok(Self {
+            bans: Mutex::new(bans),
+            path: Some(path),
+            persist_lock: Mutex::new(()),
+        })
+    }
+
+    fn parse_line(line: &str) -> anyhow::Result<Ban> {
+        let mut parts = line.splitn(3, '\t');
+        let target = parts.next().unwrap_or_default().parse()?;
+        let expires_at = match parts
+            .next()
+            .ok_or_else(|| anyhow!("missing expiry"))?
+            .parse::<u64>()?
+        {
+            0 => None,
+            secs => Some(UNIX_EPOCH + Duration::from_secs(secs)),
+        };
+        let reason = parts.next().unwrap_or_default().to_string();
+
+        Ok(Ban {
+            target,
+            reason,
+            expires_at,
+        })
+    }
+
+    /// Ban `target` for `duration`, or permanently if `None`. Replaces existing ban of the same target.
+    pub fn ban(
+        &self,
+        target: BanTarget,
+        duration: Option<Duration>,
+        reason: String,
+    ) -> anyhow::Result<()> {
+        let ban = Ban {
+            target,
+            reason,
+            expires_at: duration.map(|duration| SystemTime::now() + duration),
+        };
+        debug!("Banning {}: {}", target, ban.reason);
+        self.bans.lock().insert(target, ban);
+        self.persist()
+    }
+
+    /// Lift the ban of `target`. Returns `true` if it was banned.
+    pub fn unban(&self, target: BanTarget) -> anyhow::Result<bool> {
+        let removed = self.bans.lock().remove(&target).is_some();
+        if removed {
+            self.persist()?;
+        }
+        Ok(removed)
+    }
+
+    /// Find an active ban that applies to the given peer ID and/or IP address.
+    pub fn find(&self, id: Option<PeerId>, ip: Option<IpAddr>) -> Option<Ban> {
+        let now = SystemTime::now();
+        let mut bans = self.bans.lock();
+        bans.retain(|_, ban| !ban.is_expired(now));
+        bans.values().find(|ban| ban.matches(id, ip)).cloned()
+    }
+
+    /// All active bans.
+    pub fn bans(&self) -> Vec<Ban> {
+        let now = SystemTime::now();
+        self.bans
+            .lock()
+            .values()
+            .filter(|ban| !ban.is_expired(now))
+            .cloned()
+            .collect()
+    }
+
+    fn persist(&self) -> anyhow::Result<()> {
+        let path = if let Some(path) = &self.path {
+            path
+        } else {
+            return Ok(());
+        };
+
+        let _guard = self.persist_lock.lock();
+        let mut data = String::new();
+        for ban in self.bans() {
+            let expires_at = ban.expires_at.map_or(0, |expires_at| {
+                expires_at
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs().max(1))
+            });
+            data.push_str(&format!(
+                "{}\t{}\t{}\n",
+                ban.target,
+                expires_at,
+                ban.reason.replace(&['\t', '\n'][..], " ")
+            ));
+        }
+
+        // Write to a temporary file first so that a crash does not leave a truncated ban list.
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, data)
+            .and_then(|_| fs::rename(&tmp_path, path))
+            .with_context(|| format!("failed to persist ban list {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching() {
+        let ban_list = BanList::default();
+        let id = PeerId::random();
+        ban_list
+            .ban(BanTarget::Peer(id), None, "test".into())
+            .unwrap();
+        ban_list
+            .ban(
+                BanTarget::Subnet("10.0.0.0/8".parse().unwrap()),
+                None,
+                "test".into(),
+            )
+            .unwrap();
+        ban_list
+            .ban(
+                BanTarget::Ip("192.168.0.1".parse().unwrap()),
+                Some(Duration::from_secs(0)),
+                "expired".into(),
+            )
+            .unwrap();
+
+        assert!(ban_list.find(Some(id), None).is_some());
+        assert!(ban_list.find(Some(PeerId::random()), None).is_none());
+        assert!(ban_list
+            .find(None, Some("10.1.2.3".parse().unwrap()))
+            .is_some());
+        assert!(ban_list
+            .find(None, Some("192.168.0.1".parse().unwrap()))
+            .is_none());
+        assert_eq!(ban_list.bans().len(), 2);
+    }
+
+    #[test]
+    fn persistence() {
+        let path = std::env::temp_dir().join(format!("devp2p-bans-{}", uuid::Uuid::new_v4()));
+        let id = PeerId::random();
+
+        let ban_list = BanList::load(path.clone()).unwrap();
+        ban_list
+            .ban(
+                BanTarget::Peer(id),
+                Some(Duration::from_secs(3600)),
+                "bad\tpeer".into(),
+            )
+            .unwrap();
+        ban_list
+            .ban(BanTarget::Ip("::1".parse().unwrap()), None, "local".into())
+            .unwrap();
+
+        let reloaded = BanList::load(path.clone()).unwrap();
+        let ban = reloaded.find(Some(id), None).unwrap();
+        assert_eq!(ban.reason, "bad peer");
+        assert!(ban.expires_at.is_some());
+        assert!(reloaded
+            .find(None, Some("::1".parse().unwrap()))
+            .unwrap()
+            .expires_at
+            .is_none());
+
+        assert!(reloaded.unban(BanTarget::Peer(id)).unwrap());
+        assert!(BanList::load(path.clone())
+            .unwrap()
+            .find(Some(id), None)
+            .is_none());
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn concurrent_persistence() {
+        let path = std::env::temp_dir().join(format!("devp2p-bans-{}", uuid::Uuid::new_v4()));
+        let ban_list = std::sync::Arc::new(BanList::load(path.clone()).unwrap());
+
+        let ids = (0..8).map(|_| PeerId::random()).collect::<Vec<_>>();
+        let threads = ids
+            .iter()
+            .map(|&id| {
+                let ban_list = ban_list.clone();
+                std::thread::spawn(move || {
+                    ban_list.ban(BanTarget::Peer(id), None, "bad peer".into())
+                })
+            })
+            .collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap().unwrap();
+        }
+
+        let reloaded = BanList::load(path.clone()).unwrap();
+        assert!(ids
+            .iter()
+            .all(|&id| reloaded.find(Some(id), None).is_some()));
+
+        fs::remove_file(path).unwrap();
+    }
+}
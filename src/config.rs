@@ -0,0 +1,152 @@
+use crate::dial_scheduler::PEER_STORE_SOURCE;
+use anyhow::bail;
+use std::{collections::BTreeMap, time::Duration};
+
+/// Timeouts and intervals of a `Swarm`.
+///
+/// Set with `SwarmBuilder::with_config` and replaced on a running swarm with `Swarm::set_config`.
+/// Changes apply to connections and dials started afterwards, except ping settings, which existing
+/// sessions pick up on their next ping.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SwarmConfig {
+    /// Time given to a peer to read our disconnect message before the connection is closed.
+    pub disconnect_grace_period: Duration,
+    /// Same as `disconnect_grace_period`, for peers rejected right after handshake.
+    pub reject_grace_period: Duration,
+    /// Outbound TCP (or proxy) connection establishment.
+    pub tcp_connect_timeout: Duration,
+    /// ECIES auth/ack exchange.
+    pub ecies_timeout: Duration,
+    /// RLPx Hello exchange.
+    pub hello_timeout: Duration,
+    /// Time between pings to a connected peer.
+    pub ping_interval: Duration,
+    /// Peer is disconnected if it does not answer a ping within this time.
+    pub pong_timeout: Duration,
+    /// Time a discovery source is polled for a node before checking again whether nodes are wanted.
+    pub discovery_timeout: Duration,
+    /// Pause between rounds of dials started by the dialer.
+    pub dial_interval: Duration,
+    /// Maximum number of dials in progress at once. Static peers are dialed when due regardless, but count
+    /// towards the limit.
+    pub max_concurrent_dials: usize,
+    /// Maximum number of nodes waiting to be dialed, per discovery source. When full, the candidate with the
+    /// lowest priority is dropped.
+    pub max_queued_dials: usize,
+    /// Share of dials given to each discovery source, by name. Sources not listed have weight 1. Nodes from
+    /// the peer store are queued as `"peer store"`.
+    pub dial_source_weights: BTreeMap<String, u32>,
+}
+
+impl Default for SwarmConfig {
+    fn default() -> Self {
+        Self {
+            disconnect_grace_period: Duration::from_secs(2),
+            reject_grace_period: Duration::from_secs(1),
+            tcp_connect_timeout: Duration::from_secs(5),
+            ecies_timeout: Duration::from_secs(5),
+            hello_timeout: Duration::from_secs(5),
+            ping_interval: Duration::from_secs(60),
+            pong_timeout: Duration::from_secs(60),
+            discovery_timeout: Duration::from_secs(90),
+            dial_interval: Duration::from_millis(100),
+            max_concurrent_dials: 16,
+            max_queued_dials: 256,
+            dial_source_weights: vec![(PEER_STORE_SOURCE.to_string(), 4)]
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+impl SwarmConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for (name, value) in [
+            ("tcp_connect_timeout", self.tcp_connect_timeout),
+            ("ecies_timeout", self.ecies_timeout),
+            ("hello_timeout", self.hello_timeout),
+            ("ping_interval", self.ping_interval),
+            ("pong_timeout", self.pong_timeout),
+            ("discovery_timeout", self.discovery_timeout),
+            ("dial_interval", self.dial_interval),
+        ]
+        .iter()
+        {
+            if *value == Duration::from_secs(0) {
+                bail!("{} must not be zero", name);
+            }
+        }
+
+        if self.pong_timeout > self.ping_interval {
+            bail!(
+                "pong_timeout ({:?}) must not exceed ping_interval ({:?})",
+                self.pong_timeout,
+                self.ping_interval
+            );
+        }
+
+        if self.max_concurrent_dials == 0 {
+            bail!("max_concurrent_dials must not be zero");
+        }
+
+        if self.max_queued_dials == 0 {
+            bail!("max_queued_dials must not be zero");
+        }
+
+        if let Some((source, _)) = self
+            .dial_source_weights
+            .iter()
+            .find(|(_, weight)| **weight == 0)
+        {
+            bail!("weight of dial source {} must not be zero", source);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn dial_source_weight(&self, source: &str) -> u32 {
+        self.dial_source_weights.get(source).copied().unwrap_or(1)
+    }
+
+    /// Time it takes for a locally disconnected session to be gone, with some slack.
+    pub(crate) fn disconnect_deadline(&self) -> Duration {
+        self.disconnect_grace_period + Duration::from_secs(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        // Same as the timeouts used before they were configurable.
+        let config = SwarmConfig::default();
+        assert_eq!(
+            config.ecies_timeout + config.hello_timeout,
+            Duration::from_secs(10)
+        );
+        assert_eq!(config.pong_timeout, Duration::from_secs(60));
+        assert_eq!(config.ping_interval, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn validate() {
+        assert!(SwarmConfig::default().validate().is_ok());
+        assert!(SwarmConfig {
+            pong_timeout: Duration::from_secs(61),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        assert!(SwarmConfig {
+            hello_timeout: Duration::from_secs(0),
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        let mut config = SwarmConfig::default();
+        config.dial_source_weights.insert("discv4".to_string(), 0);
+        assert!(config.validate().is_err());
+    }
+}
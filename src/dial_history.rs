@@ -0,0 +1,172 @@
+use crate::{peer::DisconnectReason, rlpx::DialError, types::PeerId};
+use parking_lot::Mutex;
+use std::{
+    collections::{HashMap, VecDeque},
+    io,
+    net::SocketAddr,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Number of attempts kept for inspection.
+const MAX_ATTEMPTS: usize = 1024;
+/// Number of failing nodes above which those with expired backoff are forgotten.
+const MAX_BACKOFFS: usize = 16 * 1024;
+const MIN_BACKOFF: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Clone, Debug)]
+pub enum DialOutcome {
+    Success,
+    Failed(DialError),
+}
+
+impl DialOutcome {
+    /// Minimum time before the node can be dialed again after this outcome, regardless of backoff.
+    fn cooldown(&self) -> Duration {
+        match self {
+            Self::Success => Duration::from_secs(0),
+            Self::Failed(DialError::Connect(io::ErrorKind::ConnectionRefused)) => {
+                Duration::from_secs(30)
+            }
+            Self::Failed(DialError::Disconnected(DisconnectReason::TooManyPeers)) => {
+                Duration::from_secs(30)
+            }
+            Self::Failed(DialError::Connect(_))
+            | Self::Failed(DialError::Proxy(_))
+            | Self::Failed(DialError::Timeout) => Duration::from_secs(60),
+            Self::Failed(DialError::Handshake(_)) | Self::Failed(DialError::Disconnected(_)) => {
+                Duration::from_secs(5 * 60)
+            }
+        }
+    }
+}
+
+/// Outcome of an outbound connection attempt.
+#[derive(Clone, Debug)]
+pub struct DialAttempt {
+    pub id: PeerId,
+    pub addr: SocketAddr,
+    pub finished_at: SystemTime,
+    pub outcome: DialOutcome,
+}
+
+#[derive(Debug)]
+struct Backoff {
+    failures: u32,
+    until: Instant,
+}
+
+#[derive(Debug, Default)]
+struct DialHistoryInner {
+    attempts: VecDeque<DialAttempt>,
+    backoffs: HashMap<PeerId, Backoff>,
+}
+
+/// Bounded record of dial attempts, keeping failing nodes in exponential backoff.
+#[derive(Debug, Default)]
+pub(crate) struct DialHistory {
+    inner: Mutex<DialHistoryInner>,
+}
+
+impl DialHistory {
+    pub(crate) fn record(&self, id: PeerId, addr: SocketAddr, outcome: DialOutcome) {
+        let now = Instant::now();
+        let mut inner = self.inner.lock();
+
+        if let DialOutcome::Success = outcome {
+            inner.backoffs.remove(&id);
+        } else {
+            if inner.backoffs.len() >= MAX_BACKOFFS {
+                inner.backoffs.retain(|_, backoff| backoff.until > now);
+            }
+
+            let cooldown = outcome.cooldown();
+            let backoff = inner.backoffs.entry(id).or_insert(Backoff {
+                failures: 0,
+                until: now,
+            });
+            backoff.failures = backoff.failures.saturating_add(1);
+            let delay = MIN_BACKOFF
+                .checked_mul(1 << (backoff.failures - 1).min(16))
+                .map_or(MAX_BACKOFF, |delay| delay.min(MAX_BACKOFF));
+            backoff.until = now + delay.max(cooldown);
+        }
+
+        if inner.attempts.len() >= MAX_ATTEMPTS {
+            inner.attempts.pop_front();
+        }
+        inner.attempts.push_back(DialAttempt {
+            id,
+            addr,
+            finished_at: SystemTime::now(),
+            outcome,
+        });
+    }
+
+    /// Keep the node from being dialed for `duration`, without counting it as a failed attempt.
+    pub(crate) fn defer(&self, id: PeerId, duration: Duration) {
+        let now = Instant::now();
+        let mut inner = self.inner.lock();
+        if inner.backoffs.len() >= MAX_BACKOFFS {
+            inner.backoffs.retain(|_, backoff| backoff.until > now);
+        }
+        let backoff = inner.backoffs.entry(id).or_insert(Backoff {
+            failures: 0,
+            until: now,
+        });
+        backoff.until = backoff.until.max(now + duration);
+    }
+
+    /// Time left until the node may be dialed again, if it is in backoff.
+    pub(crate) fn backoff(&self, id: PeerId) -> Option<Duration> {
+        let now = Instant::now();
+        self.inner
+            .lock()
+            .backoffs
+            .get(&id)
+            .map(|backoff| backoff.until.saturating_duration_since(now))
+            .filter(|left| *left > Duration::from_secs(0))
+    }
+
+    /// Recorded attempts, oldest first.
+    pub(crate) fn attempts(&self) -> Vec<DialAttempt> {
+        self.inner.lock().attempts.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let history = DialHistory::default();
+        let id = PeerId::random();
+        let addr = "127.0.0.1:30303".parse().unwrap();
+
+        history.record(
+            id,
+            addr,
+            DialOutcome::Failed(DialError::Disconnected(DisconnectReason::TooManyPeers)),
+        );
+        let first = history.backoff(id).unwrap();
+        assert!(first > Duration::from_secs(25) && first <= Duration::from_secs(30));
+
+        history.record(id, addr, DialOutcome::Failed(DialError::Timeout));
+        assert!(history.backoff(id).unwrap() > Duration::from_secs(55));
+
+        for _ in 0..20 {
+            history.record(id, addr, DialOutcome::Failed(DialError::Timeout));
+        }
+        assert!(history.backoff(id).unwrap() <= MAX_BACKOFF);
+        assert!(history.backoff(id).unwrap() > MAX_BACKOFF - Duration::from_secs(5));
+
+        history.record(id, addr, DialOutcome::Success);
+        assert!(history.backoff(id).is_none());
+        assert_eq!(history.attempts().len(), 23);
+
+        history.defer(id, Duration::from_secs(60));
+        assert!(history.backoff(id).unwrap() > Duration::from_secs(55));
+        assert_eq!(history.attempts().len(), 23);
+    }
+}
@@ -0,0 +1,205 @@
+use crate::types::{NodeRecord, PeerId};
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+/// Source name of nodes dialed from the peer store.
+pub(crate) const PEER_STORE_SOURCE: &str = "peer store";
+
+/// Virtual time a source with weight 1 spends per dial.
+const STRIDE: u64 = 1 << 16;
+
+#[derive(Clone, Copy, Debug)]
+struct Candidate {
+    priority: i32,
+    /// Negated arrival order, so that among equals the earliest is dialed first.
+    order: i64,
+    record: NodeRecord,
+}
+
+impl Candidate {
+    fn key(&self) -> (i32, i64) {
+        (self.priority, self.order)
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+#[derive(Debug, Default)]
+struct SourceQueue {
+    weight: u32,
+    /// Stride scheduling pass: the source with the lowest pass dials next.
+    pass: u64,
+    candidates: BinaryHeap<Candidate>,
+}
+
+/// Queue of nodes waiting to be dialed, with a bound on dials in progress.
+///
+/// Each discovery source has its own queue, ordered by candidate priority. Sources take turns in proportion
+/// to their weights, so a source that produces many nodes cannot starve the others.
+#[derive(Debug, Default)]
+pub(crate) struct DialScheduler {
+    sources: HashMap<String, SourceQueue>,
+    queued: HashSet<PeerId>,
+    in_flight: HashSet<PeerId>,
+    arrivals: i64,
+}
+
+impl DialScheduler {
+    /// Queue a node found by `source`. Returns `false` if the node is already queued or being dialed, or if
+    /// the source queue holds `capacity` candidates of higher priority.
+    pub(crate) fn push(
+        &mut self,
+        source: &str,
+        weight: u32,
+        capacity: usize,
+        record: NodeRecord,
+        priority: i32,
+    ) -> bool {
+        if self.queued.contains(&record.id) || self.in_flight.contains(&record.id) {
+            return false;
+        }
+
+        // A source that was idle joins at the current pass instead of catching up on turns it did not use.
+        let current_pass = self
+            .sources
+            .values()
+            .filter(|queue| !queue.candidates.is_empty())
+            .map(|queue| queue.pass)
+            .min();
+        let queue = self.sources.entry(source.to_string()).or_default();
+        queue.weight = weight.max(1);
+        if queue.candidates.is_empty() {
+            queue.pass = queue.pass.max(current_pass.unwrap_or(0));
+        }
+
+        self.arrivals += 1;
+        let candidate = Candidate {
+            priority,
+            order: -self.arrivals,
+            record,
+        };
+        if queue.candidates.len() >= capacity {
+            match queue.candidates.iter().min().copied() {
+                Some(lowest) if lowest < candidate => {
+                    let mut candidates = std::mem::take(&mut queue.candidates).into_vec();
+                    candidates.retain(|other| *other != lowest);
+                    queue.candidates = candidates.into();
+                    self.queued.remove(&lowest.record.id);
+                }
+                _ => return false,
+            }
+        }
+
+        queue.candidates.push(candidate);
+        self.queued.insert(record.id);
+        true
+    }
+
+    /// Take the next node to dial and count it as in flight until `finish`.
+    pub(crate) fn pop(&mut self) -> Option<(String, NodeRecord)> {
+        let (source, queue) = self
+            .sources
+            .iter_mut()
+            .filter(|(_, queue)| !queue.candidates.is_empty())
+            .min_by(|(a_name, a), (b_name, b)| a.pass.cmp(&b.pass).then(a_name.cmp(b_name)))?;
+
+        let Candidate { record, .. } = queue.candidates.pop()?;
+        queue.pass += STRIDE / u64::from(queue.weight);
+        self.queued.remove(&record.id);
+        self.in_flight.insert(record.id);
+        Some((source.clone(), record))
+    }
+
+    /// Count a dial started outside of the queue as in flight. Returns `false` if the node is already dialed.
+    pub(crate) fn start(&mut self, id: PeerId) -> bool {
+        self.in_flight.insert(id)
+    }
+
+    pub(crate) fn finish(&mut self, id: PeerId) {
+        self.in_flight.remove(&id);
+    }
+
+    pub(crate) fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    #[cfg(test)]
+    pub(crate) fn queued(&self) -> usize {
+        self.queued.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record() -> NodeRecord {
+        NodeRecord {
+            addr: "1.2.3.4:30303".parse().unwrap(),
+            id: PeerId::random(),
+        }
+    }
+
+    #[test]
+    fn weighted_priority_queue() {
+        let mut scheduler = DialScheduler::default();
+
+        let discovered = (0..6).map(|_| record()).collect::<Vec<_>>();
+        for (i, &record) in discovered.iter().enumerate() {
+            assert!(scheduler.push("discv4", 1, 4, record, i as i32));
+        }
+        // The two lowest priorities were dropped to stay within capacity.
+        assert_eq!(scheduler.queued(), 4);
+        assert!(!scheduler.push("discv4", 1, 4, record(), -1));
+
+        let stored = (0..4).map(|_| record()).collect::<Vec<_>>();
+        for &record in &stored {
+            assert!(scheduler.push("peer store", 2, 4, record, 0));
+        }
+        assert!(!scheduler.push("discv4", 1, 4, stored[0], 100));
+
+        // Highest priority first within a source, the peer store gets two turns for each discv4 turn.
+        let order = std::iter::from_fn(|| scheduler.pop())
+            .map(|(source, record)| (source, record.id))
+            .collect::<Vec<_>>();
+        let expected = [
+            ("discv4", discovered[5]),
+            ("peer store", stored[0]),
+            ("peer store", stored[1]),
+            ("discv4", discovered[4]),
+            ("peer store", stored[2]),
+            ("peer store", stored[3]),
+            ("discv4", discovered[3]),
+            ("discv4", discovered[2]),
+        ]
+        .iter()
+        .map(|(source, record)| (source.to_string(), record.id))
+        .collect::<Vec<_>>();
+        assert_eq!(order, expected);
+
+        assert_eq!(scheduler.in_flight(), 8);
+        assert!(!scheduler.start(stored[0].id));
+        scheduler.finish(stored[0].id);
+        assert!(scheduler.start(stored[0].id));
+    }
+}
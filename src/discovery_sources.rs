@@ -0,0 +1,357 @@
+use crate::{config::SwarmConfig, disc::Discovery, types::NodeRecord};
+use parking_lot::{Mutex, RwLock};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::{
+    sync::{mpsc, oneshot},
+    time::sleep,
+};
+use tokio_stream::StreamExt;
+use tracing::*;
+
+/// Pause between checks whether the dialer wants nodes again.
+const IDLE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// What to do when a discovery source ends or keeps failing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestartPolicy {
+    /// Leave the source ended.
+    Never,
+    /// Recreate the source after `delay`.
+    Restart {
+        delay: Duration,
+        /// Also restart after this many errors in a row.
+        max_consecutive_errors: Option<u32>,
+        /// Give up after this many restarts.
+        max_restarts: Option<u32>,
+    },
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self::Restart {
+            delay: Duration::from_secs(30),
+            max_consecutive_errors: Some(10),
+            max_restarts: None,
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Delay before the next restart, if any, after `restarts` restarts so far.
+    pub(crate) fn restart_delay(&self, restarts: u32) -> Option<Duration> {
+        match *self {
+            Self::Never => None,
+            Self::Restart {
+                max_restarts: Some(max),
+                ..
+            } if restarts >= max => None,
+            Self::Restart { delay, .. } => Some(delay),
+        }
+    }
+
+    /// Whether the source is considered failed after `errors` errors in a row.
+    pub(crate) fn is_failed(&self, errors: u32) -> bool {
+        matches!(self, Self::Restart { max_consecutive_errors: Some(max), .. } if errors >= *max)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiscoveryState {
+    Running,
+    /// Waiting to be restarted after it ended or failed.
+    Restarting,
+    /// Ended and not restarted. Remove the source to add one under the same name.
+    Ended,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DiscoveryStats {
+    /// Nodes yielded by the source.
+    pub records: u64,
+    pub errors: u64,
+    /// Dials started to nodes from the source.
+    pub dials: u64,
+    /// Dials that ended with the node joining the peer pool.
+    pub dial_successes: u64,
+    pub restarts: u32,
+}
+
+impl DiscoveryStats {
+    pub fn dial_success_rate(&self) -> Option<f64> {
+        if self.dials == 0 {
+            return None;
+        }
+        Some(self.dial_successes as f64 / self.dials as f64)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DiscoverySourceInfo {
+    pub name: String,
+    pub state: DiscoveryState,
+    pub restart_policy: RestartPolicy,
+    pub stats: DiscoveryStats,
+}
+
+#[derive(Debug)]
+struct DiscoverySource {
+    /// Tells a removed source from a later one with the same name.
+    generation: u64,
+    state: DiscoveryState,
+    restart_policy: RestartPolicy,
+    stats: DiscoveryStats,
+    /// Dropped to stop the source task.
+    _stop: oneshot::Sender<()>,
+}
+
+/// Discovery sources of a swarm, each driven by its own task.
+#[derive(Debug)]
+pub(crate) struct DiscoverySources {
+    sources: Mutex<HashMap<String, DiscoverySource>>,
+    generation: AtomicU64,
+    /// Whether the dialer is looking for nodes. Sources are not polled otherwise.
+    wanted: AtomicBool,
+}
+
+impl Default for DiscoverySources {
+    fn default() -> Self {
+        Self {
+            sources: Default::default(),
+            generation: Default::default(),
+            wanted: AtomicBool::new(true),
+        }
+    }
+}
+
+impl DiscoverySources {
+    /// Register a source. Returns its generation and the signal to stop its task, or `None` if the name
+    /// is taken.
+    pub(crate) fn insert(
+        &self,
+        name: &str,
+        restart_policy: RestartPolicy,
+    ) -> Option<(u64, oneshot::Receiver<()>)> {
+        let mut sources = self.sources.lock();
+        match sources.entry(name.to_string()) {
+            Entry::Occupied(_) => None,
+            Entry::Vacant(entry) => {
+                let generation = self.generation.fetch_add(1, Ordering::Relaxed);
+                let (stop, stopped) = oneshot::channel();
+                entry.insert(DiscoverySource {
+                    generation,
+                    state: DiscoveryState::Running,
+                    restart_policy,
+                    stats: DiscoveryStats::default(),
+                    _stop: stop,
+                });
+                Some((generation, stopped))
+            }
+        }
+    }
+
+    pub(crate) fn remove(&self, name: &str) -> bool {
+        self.sources.lock().remove(name).is_some()
+    }
+
+    /// Remove all sources, stopping their tasks.
+    pub(crate) fn clear(&self) {
+        self.sources.lock().clear();
+    }
+
+    pub(crate) fn list(&self) -> Vec<DiscoverySourceInfo> {
+        let mut list = self
+            .sources
+            .lock()
+            .iter()
+            .map(|(name, source)| DiscoverySourceInfo {
+                name: name.clone(),
+                state: source.state,
+                restart_policy: source.restart_policy,
+                stats: source.stats,
+            })
+            .collect::<Vec<_>>();
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+
+    /// Update the state of the source task of `generation`. Returns `false` if the source was removed.
+    pub(crate) fn update(
+        &self,
+        name: &str,
+        generation: u64,
+        f: impl FnOnce(&mut DiscoveryState, &mut DiscoveryStats),
+    ) -> bool {
+        match self.sources.lock().get_mut(name) {
+            Some(source) if source.generation == generation => {
+                f(&mut source.state, &mut source.stats);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub(crate) fn record_dial(&self, name: &str, connected: bool) {
+        if let Some(source) = self.sources.lock().get_mut(name) {
+            source.stats.dials += 1;
+            if connected {
+                source.stats.dial_successes += 1;
+            }
+        }
+    }
+
+    pub(crate) fn wanted(&self) -> bool {
+        self.wanted.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_wanted(&self, wanted: bool) {
+        self.wanted.store(wanted, Ordering::Relaxed);
+    }
+}
+
+/// Task polling one discovery source and passing its nodes to the dialer.
+pub(crate) struct DiscoveryTask {
+    pub(crate) name: String,
+    pub(crate) generation: u64,
+    pub(crate) restart_policy: RestartPolicy,
+    /// Creates the stream, on start and again on each restart.
+    pub(crate) factory: Box<dyn Fn() -> Discovery + Send + Sync>,
+    pub(crate) sources: Arc<DiscoverySources>,
+    pub(crate) config: Arc<RwLock<SwarmConfig>>,
+    pub(crate) records: mpsc::Sender<(String, NodeRecord)>,
+}
+
+impl DiscoveryTask {
+    fn update(&self, f: impl FnOnce(&mut DiscoveryState, &mut DiscoveryStats)) -> bool {
+        self.sources.update(&self.name, self.generation, f)
+    }
+
+    /// Run until the source is removed, or until it ends and is not restarted.
+    pub(crate) async fn run(self, mut stopped: oneshot::Receiver<()>) {
+        let mut restarts = 0;
+        loop {
+            let mut stream = (self.factory)();
+            let mut errors = 0;
+            loop {
+                if !self.sources.wanted() {
+                    tokio::select! {
+                        _ = &mut stopped => return,
+                        _ = sleep(IDLE_INTERVAL) => continue,
+                    }
+                }
+
+                let timeout = self.config.read().discovery_timeout;
+                let next = tokio::select! {
+                    _ = &mut stopped => return,
+                    next = tokio::time::timeout(timeout, stream.next()) => next,
+                };
+                match next {
+                    Err(_) => debug!("Failed to get new peer: timed out"),
+                    Ok(Some(Ok(record))) => {
+                        errors = 0;
+                        if !self.update(|_, stats| stats.records += 1) {
+                            return;
+                        }
+                        tokio::select! {
+                            _ = &mut stopped => return,
+                            sent = self.records.send((self.name.clone(), record)) => {
+                                if sent.is_err() {
+                                    return;
+                                }
+                            }
+                        }
+                    }
+                    Ok(Some(Err(e))) => {
+                        warn!("Failed to get new peer: {}", e);
+                        errors += 1;
+                        if !self.update(|_, stats| stats.errors += 1) {
+                            return;
+                        }
+                        if self.restart_policy.is_failed(errors) {
+                            warn!("Discovery failed after {} errors in a row", errors);
+                            break;
+                        }
+                    }
+                    Ok(None) => {
+                        debug!("Discovery ended");
+                        break;
+                    }
+                }
+            }
+            drop(stream);
+
+            let delay = match self.restart_policy.restart_delay(restarts) {
+                Some(delay) => delay,
+                None => {
+                    self.update(|state, _| *state = DiscoveryState::Ended);
+                    return;
+                }
+            };
+            if !self.update(|state, _| *state = DiscoveryState::Restarting) {
+                return;
+            }
+            tokio::select! {
+                _ = &mut stopped => return,
+                _ = sleep(delay) => {}
+            }
+
+            restarts += 1;
+            if !self.update(|state, stats| {
+                *state = DiscoveryState::Running;
+                stats.restarts += 1;
+            }) {
+                return;
+            }
+            debug!("Restarting discovery");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sources() {
+        let sources = DiscoverySources::default();
+        let (generation, mut stopped) = sources.insert("dns", RestartPolicy::Never).unwrap();
+        assert!(sources.insert("dns", RestartPolicy::default()).is_none());
+
+        sources.record_dial("dns", true);
+        sources.record_dial("dns", false);
+        assert!(sources.update("dns", generation, |state, _| *state = DiscoveryState::Ended));
+        let info = sources.list();
+        assert_eq!(info[0].state, DiscoveryState::Ended);
+        assert_eq!(info[0].stats.dial_success_rate(), Some(0.5));
+
+        // The task of a removed source is stopped and cannot touch a new source of the same name.
+        assert!(sources.remove("dns"));
+        assert_eq!(
+            stopped.try_recv(),
+            Err(oneshot::error::TryRecvError::Closed)
+        );
+        sources.insert("dns", RestartPolicy::Never).unwrap();
+        assert!(!sources.update("dns", generation, |_, _| {}));
+    }
+
+    #[test]
+    fn restart_policy() {
+        let policy = RestartPolicy::Restart {
+            delay: Duration::from_secs(1),
+            max_consecutive_errors: Some(3),
+            max_restarts: Some(2),
+        };
+        assert_eq!(policy.restart_delay(1), Some(Duration::from_secs(1)));
+        assert_eq!(policy.restart_delay(2), None);
+        assert!(!policy.is_failed(2));
+        assert!(policy.is_failed(3));
+        assert_eq!(RestartPolicy::Never.restart_delay(0), None);
+        assert!(!RestartPolicy::Never.is_failed(100));
+    }
+}
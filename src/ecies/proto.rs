@@ -1,5 +1,9 @@
 use super::algorithm::ECIES;
-use crate::{errors::ECIESError, transport::Transport, types::PeerId};
+use crate::{
+    errors::{ECIESError, ProtocolBreach},
+    transport::Transport,
+    types::PeerId,
+};
 use anyhow::{bail, Context as _};
 use bytes::{Bytes, BytesMut};
 use futures::{ready, Sink, SinkExt};
@@ -116,7 +120,8 @@ impl Decoder for ECIESCodec {
                     }
 
                     self.ecies
-                        .read_header(&mut *buf.split_to(ECIES::header_len()))?;
+                        .read_header(&mut *buf.split_to(ECIES::header_len()))
+                        .map_err(|e| ProtocolBreach(format!("{:?}", e)))?;
 
                     self.state = ECIESState::Body;
                 }
@@ -126,7 +131,11 @@ impl Decoder for ECIESCodec {
                     }
 
                     let mut data = buf.split_to(self.ecies.body_len());
-                    let ret = Bytes::copy_from_slice(&self.ecies.read_body(&mut *data)?);
+                    let body = self
+                        .ecies
+                        .read_body(&mut *data)
+                        .map_err(|e| ProtocolBreach(format!("{:?}", e)))?;
+                    let ret = Bytes::copy_from_slice(body);
 
                     self.state = ECIESState::Header;
                     return Ok(Some(IngressECIESValue::Message(ret)));
@@ -246,13 +255,12 @@ where
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         match ready!(Pin::new(&mut self.get_mut().stream).poll_next(cx)) {
             Some(Ok(IngressECIESValue::Message(body))) => Poll::Ready(Some(Ok(body))),
-            Some(other) => Poll::Ready(Some(Err(io::Error::new(
-                io::ErrorKind::Other,
-                format!(
-                    "ECIES stream protocol error: expected message, received {:?}",
-                    other
-                ),
-            )))),
+            Some(Ok(other)) => Poll::Ready(Some(Err(ProtocolBreach(format!(
+                "ECIES stream protocol error: expected message, received {:?}",
+                other
+            ))
+            .into()))),
+            Some(Err(e)) => Poll::Ready(Some(Err(e))),
             None => Poll::Ready(None),
         }
     }
@@ -1,3 +1,4 @@
+use crate::{peer::DisconnectReason, types::PeerId};
 use std::io;
 use thiserror::Error;
 
@@ -32,3 +33,65 @@ impl From<rlp::DecoderError> for ECIESError {
         Self::Other(error.into())
     }
 }
+
+/// Peer has sent data that violates the protocol, as opposed to the connection failing.
+#[derive(Debug, Error)]
+#[error("protocol breach: {0}")]
+pub(crate) struct ProtocolBreach(pub(crate) String);
+
+impl From<ProtocolBreach> for io::Error {
+    fn from(error: ProtocolBreach) -> Self {
+        Self::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+impl ProtocolBreach {
+    pub(crate) fn is_cause_of(error: &io::Error) -> bool {
+        error.get_ref().is_some_and(|e| e.is::<Self>())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ProxyError {
+    #[error("IO error")]
+    IO(#[from] io::Error),
+    #[error("proxy replied with unsupported version {0}")]
+    UnsupportedVersion(u8),
+    #[error("proxy does not accept any of the offered authentication methods")]
+    NoAcceptableAuthMethod,
+    #[error("proxy rejected credentials")]
+    AuthenticationRejected,
+    #[error("invalid proxy credentials: {0}")]
+    InvalidCredentials(&'static str),
+    #[error("SOCKS5 connect request failed with reply code {0}")]
+    Socks5ConnectFailed(u8),
+    #[error("HTTP CONNECT failed with status {0}")]
+    HttpConnectFailed(u16),
+    #[error("malformed proxy response")]
+    MalformedResponse,
+}
+
+#[derive(Debug, Error)]
+pub enum HandshakeError {
+    #[error("explicit disconnect: {0}")]
+    Disconnected(DisconnectReason),
+    #[error("null node identity in Hello")]
+    NullIdentity,
+    #[error("unexpected identity in handshake: expected {expected:x}, received {received:x}")]
+    UnexpectedIdentity { expected: PeerId, received: PeerId },
+    #[error("connected to self")]
+    ConnectedToSelf,
+    #[error("{0} timed out")]
+    Timeout(&'static str),
+    #[error("ECIES handshake failed: {0}")]
+    Ecies(anyhow::Error),
+    #[error("Hello exchange failed: {0}")]
+    Hello(anyhow::Error),
+}
+
+impl HandshakeError {
+    /// Typed error of a failed Hello exchange.
+    pub(crate) fn from_hello(error: anyhow::Error) -> Self {
+        error.downcast().unwrap_or_else(Self::Hello)
+    }
+}
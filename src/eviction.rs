@@ -0,0 +1,163 @@
+use crate::{
+    net_policy::subnet,
+    rlpx::ConnectionDirection,
+    types::{CapabilityInfo, PeerId},
+};
+use auto_impl::auto_impl;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    net::{IpAddr, SocketAddr},
+    time::Instant,
+};
+
+/// Peer considered for eviction, or the peer asking for a slot.
+#[derive(Clone, Debug)]
+pub struct EvictionCandidate {
+    pub id: PeerId,
+    pub addr: SocketAddr,
+    pub direction: ConnectionDirection,
+    /// When the session was established. For the peer asking for a slot, the time of the request.
+    pub connected_at: Instant,
+    /// Negotiated capabilities. Empty for a peer we are about to dial.
+    pub capabilities: Vec<CapabilityInfo>,
+    pub reputation: i32,
+    /// Static peers are redialed after disconnect.
+    pub is_static: bool,
+}
+
+/// Chooses a peer to disconnect with `TooManyPeers` when the pool is full and another peer asks for a slot.
+///
+/// Called with the peer table locked, so it must be quick. Trusted peers and peers needed to satisfy
+/// capability reservations are never offered for eviction.
+#[auto_impl(&, Box, Arc)]
+pub trait EvictionPolicy: Debug + Send + Sync + 'static {
+    /// Pick one of `peers` to make room for `newcomer`, or `None` to turn the newcomer away.
+    fn select(&self, newcomer: &EvictionCandidate, peers: &[EvictionCandidate]) -> Option<PeerId>;
+}
+
+/// Never evicts, so new peers are turned away when the pool is full.
+impl EvictionPolicy for () {
+    fn select(&self, _: &EvictionCandidate, _: &[EvictionCandidate]) -> Option<PeerId> {
+        None
+    }
+}
+
+/// Eviction modelled after Bitcoin Core.
+///
+/// Outbound and static peers are never evicted. Of inbound peers, those with the best reputation, a few
+/// from distinct /16 subnets and the longest connected half are protected. The most recently connected
+/// peer from the most crowded subnet among the rest is evicted, unless the newcomer has a worse reputation.
+#[derive(Clone, Debug)]
+pub struct DefaultEvictionPolicy {
+    /// Number of peers with the highest reputation to protect.
+    pub protect_by_reputation: usize,
+    /// Number of peers from distinct subnets to protect.
+    pub protect_by_subnet: usize,
+}
+
+impl Default for DefaultEvictionPolicy {
+    fn default() -> Self {
+        Self {
+            protect_by_reputation: 4,
+            protect_by_subnet: 4,
+        }
+    }
+}
+
+fn netgroup(addr: SocketAddr) -> IpAddr {
+    subnet(addr.ip(), 16, 32)
+}
+
+impl EvictionPolicy for DefaultEvictionPolicy {
+    fn select(&self, newcomer: &EvictionCandidate, peers: &[EvictionCandidate]) -> Option<PeerId> {
+        let mut candidates = peers
+            .iter()
+            .filter(|peer| peer.direction == ConnectionDirection::Inbound && !peer.is_static)
+            .collect::<Vec<_>>();
+
+        candidates.sort_by_key(|peer| Reverse(peer.reputation));
+        candidates.drain(..self.protect_by_reputation.min(candidates.len()));
+
+        // Oldest peer of each subnet, oldest first.
+        candidates.sort_by_key(|peer| peer.connected_at);
+        let mut subnets = HashSet::new();
+        let mut protected = 0;
+        candidates.retain(|peer| {
+            if protected < self.protect_by_subnet && subnets.insert(netgroup(peer.addr)) {
+                protected += 1;
+                return false;
+            }
+            true
+        });
+
+        candidates.drain(..candidates.len() / 2);
+
+        let mut groups = HashMap::<_, Vec<_>>::new();
+        for peer in candidates {
+            groups.entry(netgroup(peer.addr)).or_default().push(peer);
+        }
+        let victim = groups
+            .values()
+            .max_by_key(|group| {
+                (
+                    group.len(),
+                    group.iter().map(|peer| peer.connected_at).max(),
+                )
+            })?
+            .iter()
+            .max_by_key(|peer| peer.connected_at)?;
+
+        if newcomer.reputation < victim.reputation {
+            return None;
+        }
+
+        Some(victim.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn peer(addr: &str, direction: ConnectionDirection, age: u64) -> EvictionCandidate {
+        EvictionCandidate {
+            id: PeerId::random(),
+            addr: addr.parse().unwrap(),
+            direction,
+            connected_at: Instant::now() - Duration::from_secs(age),
+            capabilities: vec![],
+            reputation: 0,
+            is_static: false,
+        }
+    }
+
+    #[test]
+    fn default_policy() {
+        let policy = DefaultEvictionPolicy {
+            protect_by_reputation: 1,
+            protect_by_subnet: 1,
+        };
+        let newcomer = peer("8.8.8.8:30303", ConnectionDirection::Inbound, 0);
+
+        let mut peers = vec![
+            peer("1.1.1.1:30303", ConnectionDirection::Outbound, 10),
+            peer("2.2.0.1:30303", ConnectionDirection::Inbound, 100),
+            peer("3.3.0.1:30303", ConnectionDirection::Inbound, 90),
+            peer("4.4.0.1:30303", ConnectionDirection::Inbound, 80),
+            peer("4.4.0.2:30303", ConnectionDirection::Inbound, 70),
+            peer("4.4.0.3:30303", ConnectionDirection::Inbound, 60),
+            peer("5.5.0.1:30303", ConnectionDirection::Inbound, 1),
+        ];
+        peers[6].reputation = 50;
+        assert!(policy.select(&newcomer, &peers[..1]).is_none());
+
+        // Best reputation, oldest subnet and the older half are protected, the crowded subnet loses its youngest peer.
+        assert_eq!(policy.select(&newcomer, &peers), Some(peers[5].id));
+
+        peers[5].reputation = 10;
+        assert_eq!(policy.select(&newcomer, &peers), None);
+    }
+}
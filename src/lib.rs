@@ -8,20 +8,49 @@
 
 #![allow(clippy::large_enum_variant, clippy::upper_case_acronyms)]
 
+mod ban_list;
+mod config;
+mod dial_history;
+mod dial_scheduler;
 mod disc;
+mod discovery_sources;
 pub mod ecies;
 mod errors;
+mod eviction;
 mod mac;
+mod net_policy;
 mod node_filter;
 mod peer;
+mod peer_store;
+mod proxy;
+mod reputation;
+mod reservations;
 mod rlpx;
+mod rotation;
+mod static_peers;
 pub mod transport;
 mod types;
 pub mod util;
 
+pub use ban_list::{Ban, BanList, BanTarget};
+pub use config::SwarmConfig;
+pub use dial_history::{DialAttempt, DialOutcome};
 pub use disc::*;
+pub use discovery_sources::{DiscoverySourceInfo, DiscoveryState, DiscoveryStats, RestartPolicy};
+pub use errors::{HandshakeError, ProxyError};
+pub use eviction::{DefaultEvictionPolicy, EvictionCandidate, EvictionPolicy};
+pub use net_policy::{NetworkPolicy, PolicyViolation};
+pub use node_filter::{NodeFilter, NodeFilterRequest, PeerCounts, PeerLimits};
 pub use peer::{DisconnectReason, PeerStream};
-pub use rlpx::{ListenOptions, Swarm, SwarmBuilder};
+pub use peer_store::{parse_enodes, FilePeerStore, PeerStore, StoredPeer};
+pub use proxy::{ProxyConfig, ProxyCredentials};
+pub use reputation::{Reputation, ReputationConfig, ReputationEvent};
+pub use reservations::{CapabilityReservation, ReservationViolation};
+pub use rlpx::{
+    ConnectingPeerInfo, ConnectionDirection, DialError, DialOptions, DisconnectInitiator,
+    ListenAddr, ListenOptions, PeerHandle, PeerInfo, PeerStats, Swarm, SwarmBuilder, SwarmEvent,
+};
+pub use rotation::OutboundRotation;
 pub use types::{
     CapabilityId, CapabilityInfo, CapabilityName, CapabilityServer, CapabilityVersion,
     InboundEvent, Message, NodeRecord, OutboundEvent, PeerId,
@@ -0,0 +1,215 @@
+use cidr::{Cidr, IpCidr};
+use derive_more::Display;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Reason an address was rejected by `NetworkPolicy`.
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum PolicyViolation {
+    #[display(fmt = "address is not in any allowed range")]
+    NotAllowed,
+    #[display(fmt = "address is in denied range {}", _0)]
+    Denied(IpCidr),
+    #[display(fmt = "address is not publicly routable")]
+    NonPublic,
+    #[display(fmt = "too many peers in the same /{} subnet", _0)]
+    SubnetLimit(u8),
+}
+
+/// Restrictions on addresses we accept connections from and dial, similar to geth's `--netrestrict`.
+///
+/// Subnet caps group IPv4 addresses by /24 and /16, IPv6 addresses by /48 and /32.
+#[derive(Clone, Debug, Default)]
+pub struct NetworkPolicy {
+    /// Only these ranges are allowed, unless empty.
+    pub allow: Vec<IpCidr>,
+    /// These ranges are always rejected.
+    pub deny: Vec<IpCidr>,
+    /// Reject private, loopback, link-local and other non-routable addresses.
+    pub public_only: bool,
+    /// Maximum number of peers within the same /24 subnet.
+    pub max_per_subnet24: Option<usize>,
+    /// Maximum number of peers within the same /16 subnet.
+    pub max_per_subnet16: Option<usize>,
+}
+
+impl NetworkPolicy {
+    /// Check the address itself against allow, deny and public-only rules.
+    pub fn check_addr(&self, ip: IpAddr) -> Result<(), PolicyViolation> {
+        if let Some(&cidr) = self.deny.iter().find(|cidr| cidr.contains(&ip)) {
+            return Err(PolicyViolation::Denied(cidr));
+        }
+
+        if !self.allow.is_empty() && !self.allow.iter().any(|cidr| cidr.contains(&ip)) {
+            return Err(PolicyViolation::NotAllowed);
+        }
+
+        if self.public_only && !is_public(ip) {
+            return Err(PolicyViolation::NonPublic);
+        }
+
+        Ok(())
+    }
+
+    /// Check whether another peer at `ip` fits within subnet caps, given addresses of existing peers.
+    pub fn check_subnets(
+        &self,
+        ip: IpAddr,
+        peers: impl IntoIterator<Item = IpAddr>,
+    ) -> Result<(), PolicyViolation> {
+        if self.max_per_subnet24.is_none() && self.max_per_subnet16.is_none() {
+            return Ok(());
+        }
+
+        let (mut in24, mut in16) = (0, 0);
+        for peer in peers {
+            if same_subnet(ip, peer, 24, 48) {
+                in24 += 1;
+            }
+            if same_subnet(ip, peer, 16, 32) {
+                in16 += 1;
+            }
+        }
+
+        if matches!(self.max_per_subnet24, Some(max) if in24 >= max) {
+            return Err(PolicyViolation::SubnetLimit(24));
+        }
+        if matches!(self.max_per_subnet16, Some(max) if in16 >= max) {
+            return Err(PolicyViolation::SubnetLimit(16));
+        }
+
+        Ok(())
+    }
+}
+
+fn same_subnet(a: IpAddr, b: IpAddr, v4_prefix: u32, v6_prefix: u32) -> bool {
+    subnet(a, v4_prefix, v6_prefix) == subnet(b, v4_prefix, v6_prefix)
+}
+
+/// Network address of the subnet `ip` belongs to.
+pub(crate) fn subnet(ip: IpAddr, v4_prefix: u32, v6_prefix: u32) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => Ipv4Addr::from(u32::from(ip) & (u32::MAX << (32 - v4_prefix))).into(),
+        IpAddr::V6(ip) => Ipv6Addr::from(u128::from(ip) & (u128::MAX << (128 - v6_prefix))).into(),
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => is_public_v6(ip),
+    }
+}
+
+fn is_public_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_documentation()
+        || ip.is_multicast()
+        // 0.0.0.0/8 "this network"
+        || a == 0
+        // 100.64.0.0/10 carrier-grade NAT
+        || (a == 100 && b & 0xc0 == 64)
+        // 192.0.0.0/24 IETF protocol assignments
+        || (a == 192 && b == 0 && c == 0)
+        // 198.18.0.0/15 benchmarking
+        || (a == 198 && b & 0xfe == 18)
+        // 240.0.0.0/4 reserved
+        || a >= 240)
+}
+
+fn is_public_v6(ip: Ipv6Addr) -> bool {
+    if let Some(ip) = ipv4_mapped(ip) {
+        return is_public_v4(ip);
+    }
+
+    let first = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // fc00::/7 unique local
+        || first & 0xfe00 == 0xfc00
+        // fe80::/10 link-local
+        || first & 0xffc0 == 0xfe80
+        // 2001:db8::/32 documentation
+        || (first == 0x2001 && ip.segments()[1] == 0xdb8))
+}
+
+fn ipv4_mapped(ip: Ipv6Addr) -> Option<Ipv4Addr> {
+    match ip.segments() {
+        [0, 0, 0, 0, 0, 0xffff, ..] => ip.to_ipv4(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addr_rules() {
+        let policy = NetworkPolicy {
+            allow: vec!["10.0.0.0/8".parse().unwrap(), "8.8.0.0/16".parse().unwrap()],
+            deny: vec!["10.1.0.0/16".parse().unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(policy.check_addr("10.2.3.4".parse().unwrap()), Ok(()));
+        assert_eq!(
+            policy.check_addr("10.1.3.4".parse().unwrap()),
+            Err(PolicyViolation::Denied("10.1.0.0/16".parse().unwrap()))
+        );
+        assert_eq!(
+            policy.check_addr("1.1.1.1".parse().unwrap()),
+            Err(PolicyViolation::NotAllowed)
+        );
+
+        let policy = NetworkPolicy {
+            public_only: true,
+            ..Default::default()
+        };
+        for ip in &[
+            "127.0.0.1",
+            "192.168.1.1",
+            "100.64.1.1",
+            "240.0.0.1",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:10.0.0.1",
+        ] {
+            assert_eq!(
+                policy.check_addr(ip.parse().unwrap()),
+                Err(PolicyViolation::NonPublic),
+                "{}",
+                ip
+            );
+        }
+        assert_eq!(policy.check_addr("8.8.8.8".parse().unwrap()), Ok(()));
+        assert_eq!(policy.check_addr("2a00:1450::1".parse().unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn subnet_caps() {
+        let policy = NetworkPolicy {
+            max_per_subnet24: Some(1),
+            max_per_subnet16: Some(2),
+            ..Default::default()
+        };
+        let peers = ["1.2.3.4".parse().unwrap(), "1.2.4.4".parse().unwrap()];
+
+        assert_eq!(
+            policy.check_subnets("1.2.3.5".parse().unwrap(), peers.iter().copied()),
+            Err(PolicyViolation::SubnetLimit(24))
+        );
+        assert_eq!(
+            policy.check_subnets("1.2.5.5".parse().unwrap(), peers.iter().copied()),
+            Err(PolicyViolation::SubnetLimit(16))
+        );
+        assert_eq!(
+            policy.check_subnets("1.3.3.5".parse().unwrap(), peers.iter().copied()),
+            Ok(())
+        );
+    }
+}
@@ -1,47 +1,107 @@
-use crate::types::PeerId;
-use std::{
-    collections::HashSet,
-    fmt::Debug,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
-    },
-};
-
-pub trait NodeFilter: Debug + Send + 'static {
-    fn max_peers(&self) -> usize;
-    fn is_banned(&self, id: PeerId) -> bool;
-    fn allow(&self, pool_size: usize, id: PeerId) -> bool {
-        pool_size < self.max_peers() && !self.is_banned(id)
+use crate::{rlpx::ConnectionDirection, types::PeerId};
+use async_trait::async_trait;
+use auto_impl::auto_impl;
+use std::{fmt::Debug, net::SocketAddr};
+
+/// Number of peers per connection direction, including outbound connections being established.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PeerCounts {
+    pub inbound: usize,
+    pub outbound: usize,
+}
+
+impl PeerCounts {
+    pub fn total(&self) -> usize {
+        self.inbound + self.outbound
+    }
+
+    pub(crate) fn add(&mut self, direction: ConnectionDirection) {
+        match direction {
+            ConnectionDirection::Inbound => self.inbound += 1,
+            ConnectionDirection::Outbound => self.outbound += 1,
+        }
     }
-    fn ban(&mut self, id: PeerId);
 }
 
-#[derive(Debug)]
-pub struct MemoryNodeFilter {
-    peer_limiter: Arc<AtomicUsize>,
-    ban_list: HashSet<PeerId>,
+/// Limits on the number of peers. Trusted peers are admitted beyond them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PeerLimits {
+    pub max_peers: usize,
+    /// Limit on inbound peers within `max_peers`, so that they cannot crowd out the peers we dial ourselves.
+    pub max_inbound: usize,
+    /// Limit on outbound peers within `max_peers`.
+    pub max_outbound: usize,
 }
 
-impl MemoryNodeFilter {
-    pub fn new(peer_limiter: Arc<AtomicUsize>) -> Self {
+impl PeerLimits {
+    /// Same limit for total, inbound and outbound peers.
+    pub fn new(max_peers: usize) -> Self {
         Self {
-            peer_limiter,
-            ban_list: Default::default(),
+            max_peers,
+            max_inbound: max_peers,
+            max_outbound: max_peers,
         }
     }
-}
 
-impl NodeFilter for MemoryNodeFilter {
-    fn max_peers(&self) -> usize {
-        self.peer_limiter.load(Ordering::Relaxed)
+    /// Whether one more peer connecting in `direction` fits.
+    pub fn allows(&self, counts: PeerCounts, direction: ConnectionDirection) -> bool {
+        counts.total() < self.max_peers
+            && match direction {
+                ConnectionDirection::Inbound => counts.inbound < self.max_inbound,
+                ConnectionDirection::Outbound => counts.outbound < self.max_outbound,
+            }
     }
+}
 
-    fn is_banned(&self, id: PeerId) -> bool {
-        self.ban_list.contains(&id)
+/// Node about to join the peer pool.
+#[derive(Clone, Copy, Debug)]
+pub struct NodeFilterRequest {
+    pub id: PeerId,
+    pub addr: SocketAddr,
+    pub direction: ConnectionDirection,
+    /// Peers connected and being dialed at the time of the request.
+    pub counts: PeerCounts,
+    /// Current reputation score of the node.
+    pub reputation: i32,
+}
+
+/// Application-defined admission policy, consulted in addition to peer limits.
+///
+/// Inbound peers are checked after the handshake, outbound ones before dialing. No internal locks are held
+/// while the filter runs, so it may query external services. Trusted peers are never filtered.
+#[async_trait]
+#[auto_impl(&, Box, Arc)]
+pub trait NodeFilter: Debug + Send + Sync + 'static {
+    async fn allow(&self, request: NodeFilterRequest) -> bool;
+}
+
+/// Admits every node.
+#[async_trait]
+impl NodeFilter for () {
+    async fn allow(&self, _: NodeFilterRequest) -> bool {
+        true
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        let limits = PeerLimits {
+            max_peers: 3,
+            max_inbound: 2,
+            max_outbound: 3,
+        };
+        let mut counts = PeerCounts::default();
+
+        counts.add(ConnectionDirection::Inbound);
+        counts.add(ConnectionDirection::Inbound);
+        assert!(!limits.allows(counts, ConnectionDirection::Inbound));
+        assert!(limits.allows(counts, ConnectionDirection::Outbound));
 
-    fn ban(&mut self, id: PeerId) {
-        self.ban_list.insert(id);
+        counts.add(ConnectionDirection::Outbound);
+        assert!(!limits.allows(counts, ConnectionDirection::Outbound));
     }
 }
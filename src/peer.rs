@@ -1,4 +1,10 @@
-use crate::{ecies::ECIESStream, transport::Transport, types::*, util::pk2id};
+use crate::{
+    ecies::ECIESStream,
+    errors::{HandshakeError, ProtocolBreach},
+    transport::Transport,
+    types::*,
+    util::pk2id,
+};
 use anyhow::{anyhow, bail, Context as _};
 use bytes::{Bytes, BytesMut};
 use derive_more::Display;
@@ -19,7 +25,7 @@ use tracing::*;
 const MAX_PAYLOAD_SIZE: usize = 16 * 1024 * 1024;
 
 /// RLPx disconnect reason.
-#[derive(Clone, Copy, Debug, Display, Primitive)]
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq, Primitive)]
 pub enum DisconnectReason {
     #[display(fmt = "disconnect requested")]
     DisconnectRequested = 0x00,
@@ -127,6 +133,8 @@ pub struct PeerStream<Io> {
     port: u16,
     id: PeerId,
     remote_id: PeerId,
+    remote_client_version: String,
+    remote_port: u16,
 
     snappy: Option<Snappy>,
 
@@ -147,6 +155,16 @@ where
         &self.shared_capabilities
     }
 
+    /// Client version announced by remote peer in Hello
+    pub fn remote_client_version(&self) -> &str {
+        &self.remote_client_version
+    }
+
+    /// Listening port announced by remote peer in Hello
+    pub fn remote_port(&self) -> u16 {
+        self.remote_port
+    }
+
     /// Connect to a peer over TCP
     #[instrument(
         skip(
@@ -276,16 +294,11 @@ where
         match message_id {
             0 => {}
             1 => {
-                let reason = Rlp::new(payload)
-                    .val_at::<u8>(0)
-                    .ok()
-                    .and_then(DisconnectReason::from_u8);
-                bail!(
-                    "explicit disconnect: {}",
-                    reason
-                        .map(|r| r.to_string())
-                        .unwrap_or_else(|| "(unknown)".to_string())
-                );
+                let reason = decode_disconnect_reason(payload);
+                if let Some(reason) = reason {
+                    return Err(HandshakeError::Disconnected(reason).into());
+                }
+                bail!("explicit disconnect: (unknown)");
             }
             _ => {
                 bail!(
@@ -332,8 +345,12 @@ where
             }),
         };
 
+        let identity_error = identity_error(val.id, transport.remote_id(), id);
+
         let mut this = Self {
             remote_id: transport.remote_id(),
+            remote_client_version: val.client_version,
+            remote_port: val.port,
             stream: transport,
             client_version: nonhello_client_version,
             port,
@@ -343,6 +360,13 @@ where
             disconnected: false,
         };
 
+        if let Some((reason, error)) = identity_error {
+            debug!("{}, disconnecting.", error);
+            let _ = this.send(PeerMessage::Disconnect(reason)).await;
+
+            return Err(error.into());
+        }
+
         if no_shared_caps {
             debug!("No shared capabilities, disconnecting.");
             let _ = this
@@ -356,6 +380,46 @@ where
     }
 }
 
+/// Checks the node ID announced in Hello against the one authenticated by ECIES and against our own.
+fn identity_error(
+    announced: PeerId,
+    remote_id: PeerId,
+    local_id: PeerId,
+) -> Option<(DisconnectReason, HandshakeError)> {
+    if announced.is_zero() {
+        Some((
+            DisconnectReason::NullNodeIdentity,
+            HandshakeError::NullIdentity,
+        ))
+    } else if announced != remote_id {
+        Some((
+            DisconnectReason::UnexpectedHandshakeIdentity,
+            HandshakeError::UnexpectedIdentity {
+                expected: remote_id,
+                received: announced,
+            },
+        ))
+    } else if announced == local_id {
+        Some((
+            DisconnectReason::ConnectedToSelf,
+            HandshakeError::ConnectedToSelf,
+        ))
+    } else {
+        None
+    }
+}
+
+/// Disconnect reason is a single-element list, though some clients send a bare value.
+fn decode_disconnect_reason(data: &[u8]) -> Option<DisconnectReason> {
+    let rlp = Rlp::new(data);
+    if rlp.is_list() {
+        rlp.val_at::<u8>(0).ok()
+    } else {
+        rlp.as_val::<u8>().ok()
+    }
+    .and_then(DisconnectReason::from_u8)
+}
+
 /// Sending message for RLPx
 #[derive(Clone, Debug)]
 pub struct SubprotocolMessage {
@@ -394,17 +458,20 @@ where
                     Ok(message_id) => {
                         let data = if let Some(snappy) = &mut s.snappy {
                             let input = &val[1..];
-                            let payload_len = snap::raw::decompress_len(input)?;
+                            let payload_len = snap::raw::decompress_len(input)
+                                .map_err(|e| ProtocolBreach(e.to_string()))?;
                             if payload_len > MAX_PAYLOAD_SIZE {
-                                return Poll::Ready(Some(Err(io::Error::new(
-                                    io::ErrorKind::InvalidInput,
-                                    format!(
-                                        "payload size ({}) exceeds limit ({} bytes)",
-                                        payload_len, MAX_PAYLOAD_SIZE
-                                    ),
-                                ))));
+                                return Poll::Ready(Some(Err(ProtocolBreach(format!(
+                                    "payload size ({}) exceeds limit ({} bytes)",
+                                    payload_len, MAX_PAYLOAD_SIZE
+                                ))
+                                .into())));
                             }
-                            let v = snappy.decoder.decompress_vec(input)?.into();
+                            let v = snappy
+                                .decoder
+                                .decompress_vec(input)
+                                .map_err(|e| ProtocolBreach(e.to_string()))?
+                                .into();
                             trace!("Decompressed raw message data: {}", hex::encode(&v));
                             v
                         } else {
@@ -415,22 +482,16 @@ where
                             match message_id {
                                 0x01 => {
                                     s.disconnected = true;
-                                    if let Some(reason) = Rlp::new(&*data)
-                                        .val_at::<u8>(0)
-                                        .ok()
-                                        .and_then(DisconnectReason::from_u8)
-                                    {
+                                    if let Some(reason) = decode_disconnect_reason(&data) {
                                         return Poll::Ready(Some(Ok(PeerMessage::Disconnect(
                                             reason,
                                         ))));
                                     } else {
-                                        return Poll::Ready(Some(Err(io::Error::new(
-                                            io::ErrorKind::Other,
-                                            format!(
-                                                "peer disconnected with malformed message: {}",
-                                                hex::encode(data)
-                                            ),
-                                        ))));
+                                        return Poll::Ready(Some(Err(ProtocolBreach(format!(
+                                            "peer disconnected with malformed message: {}",
+                                            hex::encode(data)
+                                        ))
+                                        .into())));
                                     }
                                 }
                                 0x02 => {
@@ -443,10 +504,10 @@ where
                                 }
                                 _ => {
                                     debug!("received unknown reserved message");
-                                    return Poll::Ready(Some(Err(io::Error::new(
-                                        io::ErrorKind::Other,
-                                        "unhandled reserved message",
-                                    ))));
+                                    return Poll::Ready(Some(Err(ProtocolBreach(
+                                        "unhandled reserved message".to_string(),
+                                    )
+                                    .into())));
                                 }
                             }
                         }
@@ -460,18 +521,19 @@ where
                             }
                         }
                         if index >= s.shared_capabilities.len() {
-                            return Poll::Ready(Some(Err(io::Error::new(
-                                io::ErrorKind::Other,
-                                "invalid message id (out of cap range)",
-                            ))));
+                            return Poll::Ready(Some(Err(ProtocolBreach(
+                                "invalid message id (out of cap range)".to_string(),
+                            )
+                            .into())));
                         }
                         (s.shared_capabilities[index], message_id, data)
                     }
                     Err(e) => {
-                        return Poll::Ready(Some(Err(io::Error::new(
-                            io::ErrorKind::Other,
-                            format!("message id parsing failed (invalid): {}", e),
-                        ))));
+                        return Poll::Ready(Some(Err(ProtocolBreach(format!(
+                            "message id parsing failed (invalid): {}",
+                            e
+                        ))
+                        .into())));
                     }
                 };
 
@@ -516,7 +578,7 @@ where
         let (message_id, payload) = match message {
             PeerMessage::Disconnect(reason) => {
                 this.disconnected = true;
-                (0x01, rlp::encode(&reason.to_u8().unwrap()).into())
+                (0x01, rlp::encode_list(&[reason.to_u8().unwrap()]).into())
             }
             PeerMessage::Ping => {
                 debug!("sending ping message");
@@ -598,3 +660,37 @@ where
         Pin::new(&mut self.get_mut().stream).poll_close(cx)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_checks() {
+        let local_id = PeerId::random();
+        let remote_id = PeerId::random();
+        assert!(identity_error(remote_id, remote_id, local_id).is_none());
+
+        assert!(matches!(
+            identity_error(PeerId::zero(), remote_id, local_id),
+            Some((
+                DisconnectReason::NullNodeIdentity,
+                HandshakeError::NullIdentity
+            ))
+        ));
+        assert!(matches!(
+            identity_error(local_id, remote_id, local_id),
+            Some((
+                DisconnectReason::UnexpectedHandshakeIdentity,
+                HandshakeError::UnexpectedIdentity { expected, received }
+            )) if expected == remote_id && received == local_id
+        ));
+        assert!(matches!(
+            identity_error(local_id, local_id, local_id),
+            Some((
+                DisconnectReason::ConnectedToSelf,
+                HandshakeError::ConnectedToSelf
+            ))
+        ));
+    }
+}
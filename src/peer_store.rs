@@ -0,0 +1,296 @@
+use crate::types::{NodeRecord, PeerId};
+use anyhow::{anyhow, Context as _};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    fmt::Debug,
+    fs,
+    path::PathBuf,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::*;
+
+/// Number of stored nodes above which the least recently seen ones are dropped.
+const MAX_STORED_PEERS: usize = 1024;
+
+/// Node we have successfully connected to.
+#[derive(Clone, Debug)]
+pub struct StoredPeer {
+    pub record: NodeRecord,
+    pub last_seen: SystemTime,
+    pub successes: u64,
+}
+
+/// Storage backend for known nodes.
+pub trait PeerStore: Debug + Send + Sync + 'static {
+    fn load(&self) -> anyhow::Result<Vec<StoredPeer>>;
+    fn save(&self, peers: &[StoredPeer]) -> anyhow::Result<()>;
+}
+
+/// Stores nodes in a file, one per line: `<enode>\t<last seen as UNIX seconds>\t<successes>`.
+#[derive(Debug)]
+pub struct FilePeerStore {
+    path: PathBuf,
+}
+
+impl FilePeerStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    fn parse_line(line: &str) -> anyhow::Result<StoredPeer> {
+        let mut parts = line.splitn(3, '\t');
+        let record = parts
+            .next()
+            .unwrap_or_default()
+            .parse::<NodeRecord>()
+            .map_err(|e| anyhow!("{}", e))?;
+        let last_seen = UNIX_EPOCH
+            + Duration::from_secs(
+                parts
+                    .next()
+                    .ok_or_else(|| anyhow!("missing last seen time"))?
+                    .parse()?,
+            );
+        let successes = parts
+            .next()
+            .ok_or_else(|| anyhow!("missing success count"))?
+            .parse()?;
+
+        Ok(StoredPeer {
+            record,
+            last_seen,
+            successes,
+        })
+    }
+}
+
+impl PeerStore for FilePeerStore {
+    /// Missing file is treated as an empty store.
+    fn load(&self) -> anyhow::Result<Vec<StoredPeer>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let data = fs::read_to_string(&self.path)
+            .with_context(|| format!("failed to read peer store {}", self.path.display()))?;
+        data.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                Self::parse_line(line)
+                    .with_context(|| format!("{}:{}: invalid node", self.path.display(), i + 1))
+            })
+            .collect()
+    }
+
+    fn save(&self, peers: &[StoredPeer]) -> anyhow::Result<()> {
+        let mut data = String::new();
+        for peer in peers {
+            data.push_str(&format!(
+                "{}\t{}\t{}\n",
+                peer.record,
+                peer.last_seen
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_secs()),
+                peer.successes
+            ));
+        }
+
+        // Write to a temporary file first so that a crash does not leave a truncated store.
+        let tmp_path = self.path.with_extension("tmp");
+        fs::write(&tmp_path, data)
+            .and_then(|_| fs::rename(&tmp_path, &self.path))
+            .with_context(|| format!("failed to persist peer store {}", self.path.display()))
+    }
+}
+
+/// In-memory view of known nodes, written back to `PeerStore` on flush.
+#[derive(Debug)]
+pub(crate) struct KnownPeers {
+    store: Option<Box<dyn PeerStore>>,
+    peers: Mutex<HashMap<PeerId, StoredPeer>>,
+    dirty: AtomicBool,
+}
+
+impl KnownPeers {
+    pub(crate) fn load(store: Option<Box<dyn PeerStore>>) -> anyhow::Result<Self> {
+        let peers = match &store {
+            Some(store) => store.load()?,
+            None => Vec::new(),
+        };
+        debug!("Loaded {} known peers", peers.len());
+
+        Ok(Self {
+            store,
+            peers: Mutex::new(
+                peers
+                    .into_iter()
+                    .map(|peer| (peer.record.id, peer))
+                    .collect(),
+            ),
+            dirty: AtomicBool::new(false),
+        })
+    }
+
+    /// Record successful connection to the node.
+    pub(crate) fn record_success(&self, record: NodeRecord) {
+        let mut peers = self.peers.lock();
+        let peer = peers.entry(record.id).or_insert(StoredPeer {
+            record,
+            last_seen: UNIX_EPOCH,
+            successes: 0,
+        });
+        peer.record = record;
+        peer.last_seen = SystemTime::now();
+        peer.successes += 1;
+
+        Self::truncate(&mut peers);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// Number of successful connections to the node, saturating at `i32::MAX`.
+    pub(crate) fn successes(&self, id: PeerId) -> i32 {
+        self.peers
+            .lock()
+            .get(&id)
+            .map_or(0, |peer| peer.successes.min(i32::MAX as u64) as i32)
+    }
+
+    /// Add nodes without connection history. Returns the number of new nodes.
+    pub(crate) fn import(&self, records: impl IntoIterator<Item = NodeRecord>) -> usize {
+        let mut peers = self.peers.lock();
+        let mut imported = 0;
+        for record in records {
+            peers.entry(record.id).or_insert_with(|| {
+                imported += 1;
+                StoredPeer {
+                    record,
+                    last_seen: UNIX_EPOCH,
+                    successes: 0,
+                }
+            });
+        }
+
+        Self::truncate(&mut peers);
+        self.dirty.store(true, Ordering::Relaxed);
+        imported
+    }
+
+    fn truncate(peers: &mut HashMap<PeerId, StoredPeer>) {
+        if peers.len() > MAX_STORED_PEERS {
+            let mut last_seen = peers
+                .values()
+                .map(|peer| peer.last_seen)
+                .collect::<Vec<_>>();
+            last_seen.sort_unstable();
+            let cutoff = last_seen[peers.len() - MAX_STORED_PEERS];
+            peers.retain(|_, peer| peer.last_seen >= cutoff);
+        }
+    }
+
+    /// Known nodes, most successful first.
+    pub(crate) fn peers(&self) -> Vec<StoredPeer> {
+        let mut peers = self.peers.lock().values().cloned().collect::<Vec<_>>();
+        peers.sort_by(|a, b| {
+            b.successes
+                .cmp(&a.successes)
+                .then(b.last_seen.cmp(&a.last_seen))
+        });
+        peers
+    }
+
+    /// Write changes to the store, if any.
+    pub(crate) fn flush(&self) -> anyhow::Result<()> {
+        if let Some(store) = &self.store {
+            if self.dirty.swap(false, Ordering::Relaxed) {
+                if let Err(e) = store.save(&self.peers()) {
+                    self.dirty.store(true, Ordering::Relaxed);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse an enode list, one per line. Empty lines and lines starting with `#` are ignored.
+pub fn parse_enodes(s: &str) -> anyhow::Result<Vec<NodeRecord>> {
+    s.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            line.parse::<NodeRecord>()
+                .map_err(|e| anyhow!("invalid enode {}: {}", line, e))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn persistence() {
+        let path = std::env::temp_dir().join(format!("devp2p-peers-{}", uuid::Uuid::new_v4()));
+        let a = NodeRecord {
+            id: PeerId::random(),
+            addr: "10.0.0.1:30303".parse().unwrap(),
+        };
+        let b = NodeRecord {
+            id: PeerId::random(),
+            addr: "[::1]:30304".parse().unwrap(),
+        };
+
+        let known_peers =
+            KnownPeers::load(Some(Box::new(FilePeerStore::new(path.clone())))).unwrap();
+        known_peers.record_success(a);
+        known_peers.record_success(a);
+        assert_eq!(known_peers.import(vec![a, b]), 1);
+        known_peers.flush().unwrap();
+
+        let reloaded = KnownPeers::load(Some(Box::new(FilePeerStore::new(path.clone())))).unwrap();
+        let peers = reloaded.peers();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].record.id, a.id);
+        assert_eq!(peers[0].successes, 2);
+        assert_eq!(peers[1].record.addr, b.addr);
+
+        let enodes = peers
+            .iter()
+            .map(|peer| peer.record.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(parse_enodes(&enodes).unwrap().len(), 2);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn malformed_enodes() {
+        let enode = NodeRecord {
+            id: PeerId::random(),
+            addr: "10.0.0.1:30303".parse().unwrap(),
+        }
+        .to_string();
+        assert_eq!(
+            parse_enodes(&format!("# bootnodes\n\n  {}  \n", enode))
+                .unwrap()
+                .len(),
+            1
+        );
+
+        for line in &[
+            "enode:",
+            "é",
+            "enode://",
+            "enode://1234@10.0.0.1:30303",
+            "enode://@10.0.0.1:30303",
+            &enode.replace('@', "#"),
+            &enode.replace(":30303", ""),
+        ] {
+            assert!(parse_enodes(&format!("{}\n{}", enode, line)).is_err());
+        }
+    }
+}
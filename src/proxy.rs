@@ -0,0 +1,410 @@
+//! Tunneling of outbound connections through SOCKS5 and HTTP CONNECT proxies
+
+use crate::errors::ProxyError;
+use std::net::SocketAddr;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tracing::*;
+
+const SOCKS5_VERSION: u8 = 0x05;
+const SOCKS5_AUTH_NONE: u8 = 0x00;
+const SOCKS5_AUTH_PASSWORD: u8 = 0x02;
+const SOCKS5_AUTH_UNACCEPTABLE: u8 = 0xff;
+const SOCKS5_PASSWORD_AUTH_VERSION: u8 = 0x01;
+const SOCKS5_CMD_CONNECT: u8 = 0x01;
+const SOCKS5_ATYP_IPV4: u8 = 0x01;
+const SOCKS5_ATYP_DOMAIN: u8 = 0x03;
+const SOCKS5_ATYP_IPV6: u8 = 0x04;
+
+const MAX_HTTP_RESPONSE_HEADER: usize = 8 * 1024;
+
+/// Username and password for SOCKS5 proxy authentication.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProxyCredentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// Proxy that outbound RLPx connections are tunneled through.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProxyConfig {
+    /// SOCKS5 proxy, optionally with username/password authentication.
+    Socks5 {
+        addr: SocketAddr,
+        credentials: Option<ProxyCredentials>,
+    },
+    /// HTTP proxy supporting the `CONNECT` method.
+    HttpConnect { addr: SocketAddr },
+}
+
+impl ProxyConfig {
+    /// Address of the proxy server itself.
+    pub fn addr(&self) -> SocketAddr {
+        match self {
+            Self::Socks5 { addr, .. } | Self::HttpConnect { addr } => *addr,
+        }
+    }
+
+    /// Open a TCP stream to `target` tunneled through this proxy.
+    pub async fn connect(&self, target: SocketAddr) -> Result<TcpStream, ProxyError> {
+        let mut stream = TcpStream::connect(self.addr()).await?;
+
+        trace!("Negotiating tunnel to {} via {:?}", target, self);
+        match self {
+            Self::Socks5 { credentials, .. } => {
+                socks5_handshake(&mut stream, target, credentials.as_ref()).await?
+            }
+            Self::HttpConnect { .. } => http_connect_handshake(&mut stream, target).await?,
+        }
+
+        Ok(stream)
+    }
+}
+
+async fn socks5_handshake(
+    stream: &mut TcpStream,
+    target: SocketAddr,
+    credentials: Option<&ProxyCredentials>,
+) -> Result<(), ProxyError> {
+    let greeting: &[u8] = if credentials.is_some() {
+        &[SOCKS5_VERSION, 2, SOCKS5_AUTH_NONE, SOCKS5_AUTH_PASSWORD]
+    } else {
+        &[SOCKS5_VERSION, 1, SOCKS5_AUTH_NONE]
+    };
+    stream.write_all(greeting).await?;
+
+    let mut choice = [0_u8; 2];
+    stream.read_exact(&mut choice).await?;
+    if choice[0] != SOCKS5_VERSION {
+        return Err(ProxyError::UnsupportedVersion(choice[0]));
+    }
+
+    match (choice[1], credentials) {
+        (SOCKS5_AUTH_NONE, _) => {}
+        (SOCKS5_AUTH_PASSWORD, Some(ProxyCredentials { username, password })) => {
+            if username.is_empty() || username.len() > 255 {
+                return Err(ProxyError::InvalidCredentials(
+                    "username must be 1 to 255 bytes",
+                ));
+            }
+            if password.is_empty() || password.len() > 255 {
+                return Err(ProxyError::InvalidCredentials(
+                    "password must be 1 to 255 bytes",
+                ));
+            }
+
+            let mut auth = Vec::with_capacity(3 + username.len() + password.len());
+            auth.push(SOCKS5_PASSWORD_AUTH_VERSION);
+            auth.push(username.len() as u8);
+            auth.extend_from_slice(username.as_bytes());
+            auth.push(password.len() as u8);
+            auth.extend_from_slice(password.as_bytes());
+            stream.write_all(&auth).await?;
+
+            let mut status = [0_u8; 2];
+            stream.read_exact(&mut status).await?;
+            if status[0] != SOCKS5_PASSWORD_AUTH_VERSION {
+                return Err(ProxyError::MalformedResponse);
+            }
+            if status[1] != 0 {
+                return Err(ProxyError::AuthenticationRejected);
+            }
+        }
+        (SOCKS5_AUTH_UNACCEPTABLE, _) => return Err(ProxyError::NoAcceptableAuthMethod),
+        _ => return Err(ProxyError::MalformedResponse),
+    }
+
+    let mut request = vec![SOCKS5_VERSION, SOCKS5_CMD_CONNECT, 0];
+    match target {
+        SocketAddr::V4(addr) => {
+            request.push(SOCKS5_ATYP_IPV4);
+            request.extend_from_slice(&addr.ip().octets());
+        }
+        SocketAddr::V6(addr) => {
+            request.push(SOCKS5_ATYP_IPV6);
+            request.extend_from_slice(&addr.ip().octets());
+        }
+    }
+    request.extend_from_slice(&target.port().to_be_bytes());
+    stream.write_all(&request).await?;
+
+    let mut reply = [0_u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS5_VERSION {
+        return Err(ProxyError::UnsupportedVersion(reply[0]));
+    }
+    if reply[1] != 0 {
+        return Err(ProxyError::Socks5ConnectFailed(reply[1]));
+    }
+
+    // Skip the bound address, it is of no use to us.
+    let bound_addr_len = match reply[3] {
+        SOCKS5_ATYP_IPV4 => 4,
+        SOCKS5_ATYP_IPV6 => 16,
+        SOCKS5_ATYP_DOMAIN => stream.read_u8().await? as usize,
+        _ => return Err(ProxyError::MalformedResponse),
+    };
+    let mut bound_addr = vec![0_u8; bound_addr_len + 2];
+    stream.read_exact(&mut bound_addr).await?;
+
+    Ok(())
+}
+
+async fn http_connect_handshake(
+    stream: &mut TcpStream,
+    target: SocketAddr,
+) -> Result<(), ProxyError> {
+    let request = format!(
+        "CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n\r\n",
+        target = target
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    // Read byte by byte so that we do not consume any tunneled data past the header.
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() >= MAX_HTTP_RESPONSE_HEADER {
+            return Err(ProxyError::MalformedResponse);
+        }
+        response.push(stream.read_u8().await?);
+    }
+
+    let status = std::str::from_utf8(&response)
+        .ok()
+        .and_then(|response| response.lines().next())
+        .and_then(|status_line| {
+            let mut parts = status_line.split_whitespace();
+            match parts.next() {
+                Some(version) if version.starts_with("HTTP/") => parts.next()?.parse::<u16>().ok(),
+                _ => None,
+            }
+        })
+        .ok_or(ProxyError::MalformedResponse)?;
+
+    if !(200..300).contains(&status) {
+        return Err(ProxyError::HttpConnectFailed(status));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    async fn echo_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0_u8; 5];
+            stream.read_exact(&mut buf).await.unwrap();
+            stream.write_all(&buf).await.unwrap();
+        });
+        addr
+    }
+
+    /// Minimal SOCKS5 proxy stand-in that only accepts `user`/`pass` when credentials are required.
+    /// Answers `socks`/`pass` with a bad sub-negotiation version.
+    async fn socks5_proxy(require_auth: bool) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut client, _) = listener.accept().await.unwrap();
+
+            let mut header = [0_u8; 2];
+            client.read_exact(&mut header).await.unwrap();
+            let mut methods = vec![0_u8; header[1] as usize];
+            client.read_exact(&mut methods).await.unwrap();
+
+            if require_auth {
+                if !methods.contains(&SOCKS5_AUTH_PASSWORD) {
+                    client
+                        .write_all(&[SOCKS5_VERSION, SOCKS5_AUTH_UNACCEPTABLE])
+                        .await
+                        .unwrap();
+                    return;
+                }
+                client
+                    .write_all(&[SOCKS5_VERSION, SOCKS5_AUTH_PASSWORD])
+                    .await
+                    .unwrap();
+
+                let _version = client.read_u8().await.unwrap();
+                let mut username = vec![0_u8; client.read_u8().await.unwrap() as usize];
+                client.read_exact(&mut username).await.unwrap();
+                let mut password = vec![0_u8; client.read_u8().await.unwrap() as usize];
+                client.read_exact(&mut password).await.unwrap();
+                if username == b"socks" {
+                    client.write_all(&[SOCKS5_VERSION, 0]).await.unwrap();
+                    return;
+                }
+                if username != b"user" || password != b"pass" {
+                    client.write_all(&[1, 1]).await.unwrap();
+                    return;
+                }
+                client.write_all(&[1, 0]).await.unwrap();
+            } else {
+                client
+                    .write_all(&[SOCKS5_VERSION, SOCKS5_AUTH_NONE])
+                    .await
+                    .unwrap();
+            }
+
+            let mut request = [0_u8; 4];
+            client.read_exact(&mut request).await.unwrap();
+            assert_eq!(request[3], SOCKS5_ATYP_IPV4);
+            let mut ip = [0_u8; 4];
+            client.read_exact(&mut ip).await.unwrap();
+            let port = client.read_u16().await.unwrap();
+
+            let mut upstream = TcpStream::connect(SocketAddr::from((ip, port)))
+                .await
+                .unwrap();
+            client
+                .write_all(&[SOCKS5_VERSION, 0, 0, SOCKS5_ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+                .await
+                .unwrap();
+
+            tokio::io::copy_bidirectional(&mut client, &mut upstream)
+                .await
+                .unwrap();
+        });
+        addr
+    }
+
+    /// Minimal HTTP proxy stand-in that answers every `CONNECT` with `status`.
+    async fn http_proxy(status: u16) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut client, _) = listener.accept().await.unwrap();
+
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                request.push(client.read_u8().await.unwrap());
+            }
+            let request = String::from_utf8(request).unwrap();
+            let target = request
+                .strip_prefix("CONNECT ")
+                .unwrap()
+                .split_whitespace()
+                .next()
+                .unwrap()
+                .parse::<SocketAddr>()
+                .unwrap();
+
+            client
+                .write_all(format!("HTTP/1.1 {} Whatever\r\n\r\n", status).as_bytes())
+                .await
+                .unwrap();
+            if status != 200 {
+                return;
+            }
+
+            let mut upstream = TcpStream::connect(target).await.unwrap();
+            tokio::io::copy_bidirectional(&mut client, &mut upstream)
+                .await
+                .unwrap();
+        });
+        addr
+    }
+
+    async fn assert_echo(mut stream: TcpStream) {
+        stream.write_all(b"hello").await.unwrap();
+        let mut buf = [0_u8; 5];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+    }
+
+    #[tokio::test]
+    async fn socks5_tunnel() {
+        let target = echo_server().await;
+        let proxy = ProxyConfig::Socks5 {
+            addr: socks5_proxy(false).await,
+            credentials: None,
+        };
+
+        assert_echo(proxy.connect(target).await.unwrap()).await;
+    }
+
+    #[tokio::test]
+    async fn socks5_tunnel_with_auth() {
+        let target = echo_server().await;
+        let proxy = ProxyConfig::Socks5 {
+            addr: socks5_proxy(true).await,
+            credentials: Some(ProxyCredentials {
+                username: "user".to_string(),
+                password: "pass".to_string(),
+            }),
+        };
+
+        assert_echo(proxy.connect(target).await.unwrap()).await;
+    }
+
+    #[tokio::test]
+    async fn socks5_bad_auth() {
+        let target = echo_server().await;
+        let proxy = ProxyConfig::Socks5 {
+            addr: socks5_proxy(true).await,
+            credentials: Some(ProxyCredentials {
+                username: "user".to_string(),
+                password: "wrong".to_string(),
+            }),
+        };
+
+        assert!(matches!(
+            proxy.connect(target).await,
+            Err(ProxyError::AuthenticationRejected)
+        ));
+
+        let proxy = ProxyConfig::Socks5 {
+            addr: socks5_proxy(true).await,
+            credentials: None,
+        };
+
+        assert!(matches!(
+            proxy.connect(target).await,
+            Err(ProxyError::NoAcceptableAuthMethod)
+        ));
+
+        let proxy = ProxyConfig::Socks5 {
+            addr: socks5_proxy(true).await,
+            credentials: Some(ProxyCredentials {
+                username: "socks".to_string(),
+                password: "pass".to_string(),
+            }),
+        };
+
+        assert!(matches!(
+            proxy.connect(target).await,
+            Err(ProxyError::MalformedResponse)
+        ));
+    }
+
+    #[tokio::test]
+    async fn http_connect_tunnel() {
+        let target = echo_server().await;
+        let proxy = ProxyConfig::HttpConnect {
+            addr: http_proxy(200).await,
+        };
+
+        assert_echo(proxy.connect(target).await.unwrap()).await;
+    }
+
+    #[tokio::test]
+    async fn http_connect_refused() {
+        let target = echo_server().await;
+        let proxy = ProxyConfig::HttpConnect {
+            addr: http_proxy(403).await,
+        };
+
+        assert!(matches!(
+            proxy.connect(target).await,
+            Err(ProxyError::HttpConnectFailed(403))
+        ));
+    }
+}
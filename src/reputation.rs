@@ -0,0 +1,200 @@
+use crate::{
+    ban_list::{BanList, BanTarget},
+    types::PeerId,
+};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::*;
+
+/// Number of tracked peers above which negligible scores are forgotten.
+const MAX_TRACKED_PEERS: usize = 16 * 1024;
+
+/// Peer behaviour reported to the reputation subsystem.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReputationEvent {
+    /// Peer did not respond in time.
+    Timeout,
+    /// Peer sent a malformed or otherwise invalid message.
+    ProtocolBreach,
+    /// Peer responded with something we needed.
+    UsefulResponse,
+    /// Peer failed RLPx handshake.
+    HandshakeFailure,
+    /// Application-specific score change.
+    Custom(i32),
+}
+
+impl ReputationEvent {
+    pub fn score_delta(self) -> i32 {
+        match self {
+            Self::Timeout => -10,
+            Self::ProtocolBreach => -50,
+            Self::UsefulResponse => 1,
+            Self::HandshakeFailure => -20,
+            Self::Custom(delta) => delta,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ReputationConfig {
+    /// Time it takes for a score to decay halfway towards zero.
+    pub half_life: Duration,
+    /// Scores are clamped to `[-max_score, max_score]`.
+    pub max_score: i32,
+    /// Peers whose score drops to this value are disconnected and banned.
+    pub ban_threshold: i32,
+    /// How long peers reaching `ban_threshold` stay banned.
+    pub ban_duration: Duration,
+    /// Dialer does not connect to nodes below this score.
+    pub dial_threshold: i32,
+}
+
+impl Default for ReputationConfig {
+    fn default() -> Self {
+        Self {
+            half_life: Duration::from_secs(10 * 60),
+            max_score: 100,
+            ban_threshold: -100,
+            ban_duration: Duration::from_secs(60 * 60),
+            dial_threshold: -50,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Score {
+    value: f64,
+    updated_at: Instant,
+}
+
+impl Score {
+    fn decayed(&self, now: Instant, half_life: Duration) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        self.value * 0.5_f64.powf(elapsed.as_secs_f64() / half_life.as_secs_f64())
+    }
+}
+
+/// Decaying per-peer reputation scores.
+#[derive(Debug)]
+pub struct Reputation {
+    config: ReputationConfig,
+    ban_list: Arc<BanList>,
+    scores: Mutex<HashMap<PeerId, Score>>,
+}
+
+impl Reputation {
+    pub fn new(config: ReputationConfig, ban_list: Arc<BanList>) -> Self {
+        Self {
+            config,
+            ban_list,
+            scores: Default::default(),
+        }
+    }
+
+    pub fn config(&self) -> &ReputationConfig {
+        &self.config
+    }
+
+    /// Apply `event` to the peer's score.
+    /// Returns `true` if the peer has reached ban threshold and has been banned, so it must be disconnected.
+    pub fn report(&self, id: PeerId, event: ReputationEvent) -> bool {
+        let now = Instant::now();
+        let value = {
+            let mut scores = self.scores.lock();
+
+            if scores.len() >= MAX_TRACKED_PEERS {
+                let half_life = self.config.half_life;
+                scores.retain(|_, score| score.decayed(now, half_life).abs() >= 1.0);
+            }
+
+            let score = scores.entry(id).or_insert(Score {
+                value: 0.0,
+                updated_at: now,
+            });
+            let max_score = f64::from(self.config.max_score);
+            score.value = (score.decayed(now, self.config.half_life)
+                + f64::from(event.score_delta()))
+            .max(-max_score)
+            .min(max_score);
+            score.updated_at = now;
+            score.value
+        };
+        trace!("Peer {} reputation {:?}: {}", id, event, value);
+
+        if value <= f64::from(self.config.ban_threshold) {
+            debug!("Peer {} reputation fell to {}, banning", id, value);
+            if let Err(e) = self.ban_list.ban(
+                BanTarget::Peer(id),
+                Some(self.config.ban_duration),
+                format!("reputation fell to {}", value.round()),
+            ) {
+                warn!("Failed to ban peer {}: {}", id, e);
+            }
+            self.scores.lock().remove(&id);
+            return true;
+        }
+
+        false
+    }
+
+    /// Current score of the peer, zero if unknown.
+    pub fn score(&self, id: PeerId) -> i32 {
+        self.scores.lock().get(&id).map_or(0, |score| {
+            score.decayed(Instant::now(), self.config.half_life).round() as i32
+        })
+    }
+
+    /// Current scores of all tracked peers.
+    pub fn scores(&self) -> HashMap<PeerId, i32> {
+        let now = Instant::now();
+        self.scores
+            .lock()
+            .iter()
+            .map(|(&id, score)| (id, score.decayed(now, self.config.half_life).round() as i32))
+            .collect()
+    }
+
+    /// Whether the dialer may connect to this node.
+    pub fn allows_dial(&self, id: PeerId) -> bool {
+        self.score(id) >= self.config.dial_threshold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ban_below_threshold() {
+        let ban_list = Arc::new(BanList::default());
+        let reputation = Reputation::new(ReputationConfig::default(), ban_list.clone());
+        let id = PeerId::random();
+
+        assert!(!reputation.report(id, ReputationEvent::ProtocolBreach));
+        assert_eq!(reputation.score(id), -50);
+        assert!(reputation.allows_dial(id));
+        assert!(!reputation.report(id, ReputationEvent::Timeout));
+        assert!(!reputation.allows_dial(id));
+        assert!(reputation.report(id, ReputationEvent::Custom(-41)));
+        assert!(ban_list.find(Some(id), None).is_some());
+        assert_eq!(reputation.score(id), 0);
+    }
+
+    #[test]
+    fn decay() {
+        let score = Score {
+            value: -80.0,
+            updated_at: Instant::now(),
+        };
+        let half_life = Duration::from_secs(60);
+
+        assert!(
+            (score.decayed(score.updated_at + half_life, half_life) - -40.0).abs() < f64::EPSILON
+        );
+    }
+}
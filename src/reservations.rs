@@ -0,0 +1,171 @@
+use crate::types::{CapabilityInfo, CapabilityName};
+use derive_more::Display;
+use std::collections::HashMap;
+
+/// Peer slots kept for peers sharing a capability, in any version.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CapabilityReservation {
+    pub capability: CapabilityName,
+    /// Hold back slots until at least this many connected peers share the capability.
+    pub min_peers: usize,
+    /// Admit at most this many peers not sharing the capability.
+    pub max_without: Option<usize>,
+}
+
+/// Reason a peer does not fit capability reservations.
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum ReservationViolation {
+    #[display(fmt = "remaining slots are reserved for {} peers", _0)]
+    Reserved(CapabilityName),
+    #[display(fmt = "too many peers without {}", _0)]
+    TooManyWithout(CapabilityName),
+}
+
+fn shares(caps: &[CapabilityInfo], capability: CapabilityName) -> bool {
+    caps.iter().any(|cap| cap.name == capability)
+}
+
+/// Peer pool as seen by reservations.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct CapabilityTally {
+    /// All peers, including those being connected.
+    total: usize,
+    connected: usize,
+    /// Number of connected peers sharing each capability.
+    sharing: HashMap<CapabilityName, usize>,
+}
+
+impl CapabilityTally {
+    pub(crate) fn add_connecting(&mut self) {
+        self.total += 1;
+    }
+
+    pub(crate) fn add_connected(&mut self, caps: &[CapabilityInfo]) {
+        self.total += 1;
+        self.connected += 1;
+        for cap in caps {
+            *self.sharing.entry(cap.name).or_default() += 1;
+        }
+    }
+
+    fn sharing(&self, capability: CapabilityName) -> usize {
+        self.sharing.get(&capability).copied().unwrap_or(0)
+    }
+
+    /// Whether a peer negotiating `caps` may take one of `max_peers` slots without eating into reservations.
+    pub(crate) fn check(
+        &self,
+        reservations: &[CapabilityReservation],
+        max_peers: usize,
+        caps: &[CapabilityInfo],
+    ) -> Result<(), ReservationViolation> {
+        let mut reserved = 0;
+        let mut unmet = None;
+        for reservation in reservations {
+            let sharing = self.sharing(reservation.capability);
+            if shares(caps, reservation.capability) {
+                reserved += reservation.min_peers.saturating_sub(sharing + 1);
+                continue;
+            }
+
+            if matches!(reservation.max_without, Some(max) if self.connected - sharing >= max) {
+                return Err(ReservationViolation::TooManyWithout(reservation.capability));
+            }
+            if sharing < reservation.min_peers {
+                reserved += reservation.min_peers - sharing;
+                unmet.get_or_insert(reservation.capability);
+            }
+        }
+
+        match unmet {
+            Some(capability) if self.total + 1 + reserved > max_peers => {
+                Err(ReservationViolation::Reserved(capability))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether a peer negotiating `caps` would fill an unmet reservation.
+    pub(crate) fn is_wanted(
+        &self,
+        reservations: &[CapabilityReservation],
+        caps: &[CapabilityInfo],
+    ) -> bool {
+        reservations.iter().any(|reservation| {
+            shares(caps, reservation.capability)
+                && self.sharing(reservation.capability) < reservation.min_peers
+        })
+    }
+
+    /// Whether any reservation is unmet.
+    pub(crate) fn has_unmet(&self, reservations: &[CapabilityReservation]) -> bool {
+        reservations
+            .iter()
+            .any(|reservation| self.sharing(reservation.capability) < reservation.min_peers)
+    }
+
+    /// Whether a connected peer negotiating `caps` can leave without breaking any reservation.
+    pub(crate) fn is_surplus(
+        &self,
+        reservations: &[CapabilityReservation],
+        caps: &[CapabilityInfo],
+    ) -> bool {
+        reservations.iter().all(|reservation| {
+            !shares(caps, reservation.capability)
+                || self.sharing(reservation.capability) > reservation.min_peers
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrayvec::ArrayString;
+
+    fn cap(name: &str) -> CapabilityInfo {
+        CapabilityInfo {
+            name: CapabilityName(ArrayString::from(name).unwrap()),
+            version: 1,
+            length: 8,
+        }
+    }
+
+    #[test]
+    fn reservations() {
+        let (snap, eth) = (cap("snap"), cap("eth"));
+        let reservations = [
+            CapabilityReservation {
+                capability: snap.name,
+                min_peers: 2,
+                max_without: None,
+            },
+            CapabilityReservation {
+                capability: eth.name,
+                min_peers: 0,
+                max_without: Some(1),
+            },
+        ];
+
+        let mut tally = CapabilityTally::default();
+        tally.add_connected(&[eth]);
+        tally.add_connecting();
+
+        // Two of four slots are held for snap peers.
+        assert_eq!(
+            tally.check(&reservations, 4, &[eth]),
+            Err(ReservationViolation::Reserved(snap.name))
+        );
+        assert_eq!(tally.check(&reservations, 5, &[eth]), Ok(()));
+        assert_eq!(tally.check(&reservations, 4, &[eth, snap]), Ok(()));
+        assert!(tally.is_wanted(&reservations, &[snap]));
+
+        tally.add_connected(&[snap]);
+        assert_eq!(
+            tally.check(&reservations, 10, &[snap]),
+            Err(ReservationViolation::TooManyWithout(eth.name))
+        );
+        assert!(!tally.is_surplus(&reservations, &[snap]));
+        assert!(tally.is_surplus(&reservations, &[eth]));
+        assert!(tally.has_unmet(&reservations));
+    }
+}
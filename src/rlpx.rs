@@ -1,48 +1,84 @@
 //! RLPx protocol implementation in Rust
 
-use crate::{disc::Discovery, node_filter::*, peer::*, transport::Transport, types::*};
+use crate::{
+    ban_list::{Ban, BanList, BanTarget},
+    config::SwarmConfig,
+    dial_history::{DialAttempt, DialHistory, DialOutcome},
+    dial_scheduler::{DialScheduler, PEER_STORE_SOURCE},
+    disc::Discovery,
+    discovery_sources::{DiscoverySourceInfo, DiscoverySources, DiscoveryTask, RestartPolicy},
+    ecies::ECIESStream,
+    errors::{HandshakeError, ProtocolBreach, ProxyError},
+    eviction::{DefaultEvictionPolicy, EvictionCandidate, EvictionPolicy},
+    net_policy::NetworkPolicy,
+    node_filter::*,
+    peer::*,
+    peer_store::{parse_enodes, KnownPeers, PeerStore, StoredPeer},
+    proxy::ProxyConfig,
+    reputation::{Reputation, ReputationConfig, ReputationEvent},
+    reservations::{CapabilityReservation, CapabilityTally, ReservationViolation},
+    rotation::OutboundRotation,
+    static_peers::StaticPeers,
+    transport::Transport,
+    types::*,
+    util::pk2id,
+};
 use anyhow::{anyhow, bail};
 use cidr::{Cidr, IpCidr};
+use derive_more::Display;
 use educe::Educe;
-use futures::sink::SinkExt;
-use parking_lot::Mutex;
-use secp256k1::SecretKey;
+use futures::{sink::SinkExt, FutureExt, Stream};
+use parking_lot::{Mutex, RwLock};
+use secp256k1::{PublicKey, SecretKey, SECP256K1};
+use socket2::{Domain, Protocol, Socket, Type};
 use std::{
     collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
     fmt::Debug,
     future::Future,
-    net::SocketAddr,
+    io,
+    net::{IpAddr, SocketAddr},
     ops::Deref,
+    path::PathBuf,
     sync::{
-        atomic::{AtomicBool, AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
         Arc, Weak,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 use task_group::TaskGroup;
 use tokio::{
     net::{TcpListener, TcpStream},
     sync::{
-        mpsc::{channel, unbounded_channel},
+        broadcast,
+        mpsc::{channel, unbounded_channel, Sender},
         oneshot::{channel as oneshot, Sender as OneshotSender},
+        watch,
     },
     time::sleep,
 };
-use tokio_stream::{StreamExt, StreamMap};
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    StreamExt, StreamMap,
+};
 use tracing::*;
 use uuid::Uuid;
 
-const GRACE_PERIOD_SECS: u64 = 2;
-const HANDSHAKE_TIMEOUT_SECS: u64 = 10;
-const PING_TIMEOUT: Duration = Duration::from_secs(60);
-const DISCOVERY_TIMEOUT_SECS: u64 = 90;
-const DISCOVERY_CONNECT_TIMEOUT_SECS: u64 = 5;
-const DIAL_INTERVAL: Duration = Duration::from_millis(100);
-
-#[derive(Clone, Copy)]
-enum DisconnectInitiator {
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+const PEER_STORE_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+const STATIC_DIAL_INTERVAL: Duration = Duration::from_secs(1);
+const LISTEN_BACKLOG: i32 = 1024;
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+const PEER_COMMAND_CHANNEL_CAPACITY: usize = 64;
+const DISCOVERY_CHANNEL_CAPACITY: usize = 64;
+
+/// Which side has ended the session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DisconnectInitiator {
+    /// We have requested disconnection.
     Local,
+    /// We have dropped the connection due to a transport error.
     LocalForceful,
+    /// Remote peer has requested disconnection or closed the connection.
     Remote,
 }
 
@@ -51,14 +87,243 @@ struct DisconnectSignal {
     reason: DisconnectReason,
 }
 
+/// Which side initiated the connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ConnectionDirection {
+    Inbound,
+    Outbound,
+}
+
+/// Information about a connected peer.
+#[derive(Clone, Debug)]
+pub struct PeerInfo {
+    pub id: PeerId,
+    pub addr: SocketAddr,
+    pub direction: ConnectionDirection,
+    pub connected_at: Instant,
+    /// Capabilities negotiated with this peer.
+    pub capabilities: Vec<CapabilityInfo>,
+    /// Client version announced by the peer.
+    pub client_version: String,
+}
+
+/// Information about a peer we are establishing connection with.
+#[derive(Clone, Debug)]
+pub struct ConnectingPeerInfo {
+    pub id: PeerId,
+    pub addr: SocketAddr,
+    pub direction: ConnectionDirection,
+    pub started_at: Instant,
+}
+
+/// Reason an outbound connection attempt has failed.
+#[derive(Clone, Debug, Display)]
+pub enum DialError {
+    #[display(fmt = "TCP connect failed: {:?}", _0)]
+    Connect(io::ErrorKind),
+    #[display(fmt = "proxy negotiation failed: {}", _0)]
+    Proxy(Arc<ProxyError>),
+    #[display(fmt = "timed out")]
+    Timeout,
+    #[display(fmt = "handshake failed: {}", _0)]
+    Handshake(Arc<HandshakeError>),
+    #[display(fmt = "remote disconnected: {}", _0)]
+    Disconnected(DisconnectReason),
+}
+
+impl std::error::Error for DialError {}
+
+/// Lifecycle event emitted by `Swarm`.
+#[derive(Clone, Debug)]
+pub enum SwarmEvent {
+    DialStarted {
+        id: PeerId,
+        addr: SocketAddr,
+    },
+    DialFailed {
+        id: PeerId,
+        addr: SocketAddr,
+        error: DialError,
+    },
+    HandshakeFailed {
+        /// Known in advance for outbound connections only.
+        id: Option<PeerId>,
+        addr: SocketAddr,
+        direction: ConnectionDirection,
+        error: Arc<HandshakeError>,
+    },
+    PeerConnected(PeerInfo),
+    PeerDisconnected {
+        id: PeerId,
+        initiator: DisconnectInitiator,
+        reason: DisconnectReason,
+    },
+    NodeFilterRejected {
+        id: PeerId,
+        addr: SocketAddr,
+        direction: ConnectionDirection,
+    },
+}
+
+/// Traffic statistics of a connected peer. Only subprotocol messages are accounted for.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PeerStats {
+    pub messages_in: u64,
+    pub messages_out: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+}
+
+#[derive(Debug, Default)]
+struct PeerCounters {
+    messages_in: AtomicU64,
+    messages_out: AtomicU64,
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+}
+
+impl PeerCounters {
+    fn record_in(&self, message: &Message) {
+        self.messages_in.fetch_add(1, Ordering::Relaxed);
+        self.bytes_in
+            .fetch_add(message.data.len() as u64, Ordering::Relaxed);
+    }
+
+    fn record_out(&self, message: &Message) {
+        self.messages_out.fetch_add(1, Ordering::Relaxed);
+        self.bytes_out
+            .fetch_add(message.data.len() as u64, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> PeerStats {
+        PeerStats {
+            messages_in: self.messages_in.load(Ordering::Relaxed),
+            messages_out: self.messages_out.load(Ordering::Relaxed),
+            bytes_in: self.bytes_in.load(Ordering::Relaxed),
+            bytes_out: self.bytes_out.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Command from `PeerHandle`, handled by the peer's egress router.
+#[derive(Debug)]
+enum PeerCommand {
+    Message {
+        capability_name: CapabilityName,
+        message: Message,
+    },
+    Disconnect(DisconnectReason),
+}
+
+/// Handle to a connected peer.
+///
+/// Messages sent through the handle are routed through the same egress router
+/// as events from `CapabilityServer::next`, so their relative order is preserved.
+#[derive(Clone, Debug)]
+pub struct PeerHandle {
+    info: PeerInfo,
+    counters: Arc<PeerCounters>,
+    commands: Sender<PeerCommand>,
+}
+
+impl PeerHandle {
+    pub fn id(&self) -> PeerId {
+        self.info.id
+    }
+
+    pub fn info(&self) -> &PeerInfo {
+        &self.info
+    }
+
+    pub fn stats(&self) -> PeerStats {
+        self.counters.snapshot()
+    }
+
+    /// Whether the peer's session is still alive.
+    pub fn is_connected(&self) -> bool {
+        !self.commands.is_closed()
+    }
+
+    /// Send a subprotocol message to this peer. Fails for capabilities not negotiated with the peer.
+    pub async fn send(
+        &self,
+        capability_name: CapabilityName,
+        message: Message,
+    ) -> anyhow::Result<()> {
+        if !is_shared(&self.info.capabilities, capability_name, message.id) {
+            bail!(
+                "capability {} message {} is not shared with peer {}",
+                capability_name.0,
+                message.id,
+                self.info.id
+            );
+        }
+        self.commands
+            .send(PeerCommand::Message {
+                capability_name,
+                message,
+            })
+            .await
+            .map_err(|_| anyhow!("peer {} is disconnected", self.info.id))
+    }
+
+    /// Disconnect this peer with the given reason.
+    pub async fn disconnect(&self, reason: DisconnectReason) -> anyhow::Result<()> {
+        self.commands
+            .send(PeerCommand::Disconnect(reason))
+            .await
+            .map_err(|_| anyhow!("peer {} is disconnected", self.info.id))
+    }
+}
+
+/// Whether message `id` of capability `name` can be sent to a peer with the given negotiated capabilities.
+fn is_shared(capabilities: &[CapabilityInfo], name: CapabilityName, id: usize) -> bool {
+    capabilities
+        .iter()
+        .any(|cap| cap.name == name && id < cap.length)
+}
+
 #[derive(Debug)]
 struct ConnectedPeerState {
+    connection_id: Uuid,
     tasks: TaskGroup,
+    info: PeerInfo,
+    counters: Arc<PeerCounters>,
+    commands: Sender<PeerCommand>,
+}
+
+impl ConnectedPeerState {
+    fn handle(&self) -> PeerHandle {
+        PeerHandle {
+            info: self.info.clone(),
+            counters: self.counters.clone(),
+            commands: self.commands.clone(),
+        }
+    }
+
+    fn eviction_candidate(
+        &self,
+        reputation: &Reputation,
+        static_peers: &StaticPeers,
+    ) -> EvictionCandidate {
+        EvictionCandidate {
+            id: self.info.id,
+            addr: self.info.addr,
+            direction: self.info.direction,
+            connected_at: self.info.connected_at,
+            capabilities: self.info.capabilities.clone(),
+            reputation: reputation.score(self.info.id),
+            is_static: static_peers.contains(self.info.id),
+        }
+    }
 }
 
 #[derive(Debug)]
 enum PeerState {
-    Connecting { connection_id: Uuid },
+    Connecting {
+        connection_id: Uuid,
+        info: ConnectingPeerInfo,
+    },
     Connected(ConnectedPeerState),
 }
 
@@ -66,6 +331,38 @@ impl PeerState {
     const fn is_connected(&self) -> bool {
         matches!(self, Self::Connected(_))
     }
+
+    fn addr(&self) -> SocketAddr {
+        match self {
+            Self::Connecting { info, .. } => info.addr,
+            Self::Connected(ConnectedPeerState { info, .. }) => info.addr,
+        }
+    }
+
+    fn direction(&self) -> ConnectionDirection {
+        match self {
+            Self::Connecting { info, .. } => info.direction,
+            Self::Connected(ConnectedPeerState { info, .. }) => info.direction,
+        }
+    }
+}
+
+/// Room for a peer that has completed handshake.
+enum Slot {
+    Free,
+    /// Room is made by evicting another peer.
+    Evict(PeerId, Sender<PeerCommand>),
+    Full(Option<ReservationViolation>),
+}
+
+/// Limits and policies deciding whether a peer gets a slot.
+struct SlotRules<'a> {
+    limits: PeerLimits,
+    reservations: &'a [CapabilityReservation],
+    eviction_policy: &'a dyn EvictionPolicy,
+    trusted_peers: &'a HashSet<PeerId>,
+    static_peers: &'a StaticPeers,
+    reputation: &'a Reputation,
 }
 
 #[derive(Debug)]
@@ -75,10 +372,152 @@ struct PeerStreams {
 }
 
 impl PeerStreams {
-    fn disconnect_peer(&mut self, remote_id: PeerId) -> bool {
-        debug!("disconnecting peer {}", remote_id);
+    fn counts(&self) -> PeerCounts {
+        let mut counts = PeerCounts::default();
+        for state in self.mapping.values() {
+            counts.add(state.direction());
+        }
+        counts
+    }
+
+    /// Capability reservations' view of all peers except `exclude`.
+    fn tally(&self, exclude: Option<PeerId>) -> CapabilityTally {
+        let mut tally = CapabilityTally::default();
+        for (&id, state) in &self.mapping {
+            match state {
+                _ if Some(id) == exclude => {}
+                PeerState::Connecting { .. } => tally.add_connecting(),
+                PeerState::Connected(ConnectedPeerState { info, .. }) => {
+                    tally.add_connected(&info.capabilities)
+                }
+            }
+        }
+        tally
+    }
+
+    /// Whether to dial another peer: either there is a free outbound slot, or some reservation is unmet
+    /// or the eviction policy would free a slot for a new outbound peer.
+    fn wants_outbound(&self, rules: &SlotRules<'_>) -> bool {
+        self.outbound_slots(rules) > 0
+    }
+
+    /// Number of outbound peers we could take. While a reservation is unmet, or while the eviction policy
+    /// would make room for an outbound peer, one more than the limits allow.
+    fn outbound_slots(&self, rules: &SlotRules<'_>) -> usize {
+        let limits = rules.limits;
+        let counts = self.counts();
+        let free = limits
+            .max_peers
+            .saturating_sub(counts.total())
+            .min(limits.max_outbound.saturating_sub(counts.outbound));
+        if free > 0 || counts.outbound >= limits.max_outbound {
+            return free;
+        }
+        if self.tally(None).has_unmet(rules.reservations) {
+            return 1;
+        }
+
+        // Peer we are about to dial, not known yet.
+        let newcomer = EvictionCandidate {
+            id: PeerId::zero(),
+            addr: SocketAddr::from(([0, 0, 0, 0], 0)),
+            direction: ConnectionDirection::Outbound,
+            connected_at: Instant::now(),
+            capabilities: vec![],
+            reputation: 0,
+            is_static: false,
+        };
+        match self.slot(&newcomer, rules) {
+            Slot::Evict(..) => 1,
+            _ => 0,
+        }
+    }
+
+    /// Find a slot for a peer that has completed handshake. If there is no room, a peer filling an unmet
+    /// reservation displaces a surplus peer, others are subject to the eviction policy.
+    fn slot(&self, newcomer: &EvictionCandidate, rules: &SlotRules<'_>) -> Slot {
+        let id = newcomer.id;
+        let direction = newcomer.direction;
+        let mut counts = PeerCounts::default();
+        for (_, state) in self.mapping.iter().filter(|&(&other, _)| other != id) {
+            counts.add(state.direction());
+        }
+        let tally = self.tally(Some(id));
+
+        let res = tally.check(
+            rules.reservations,
+            rules.limits.max_peers,
+            &newcomer.capabilities,
+        );
+        if res.is_ok() && rules.limits.allows(counts, direction) {
+            return Slot::Free;
+        }
+
+        let wanted = tally.is_wanted(rules.reservations, &newcomer.capabilities);
+        if !wanted && res.is_err() {
+            return Slot::Full(res.err());
+        }
+
+        let direction_full = match direction {
+            ConnectionDirection::Inbound => counts.inbound >= rules.limits.max_inbound,
+            ConnectionDirection::Outbound => counts.outbound >= rules.limits.max_outbound,
+        };
+        let evictable = self
+            .mapping
+            .values()
+            .filter_map(|state| match state {
+                PeerState::Connected(state) => Some(state),
+                PeerState::Connecting { .. } => None,
+            })
+            .filter(|state| {
+                state.info.id != id
+                    && !rules.trusted_peers.contains(&state.info.id)
+                    && (!direction_full || state.info.direction == direction)
+                    && tally.is_surplus(rules.reservations, &state.info.capabilities)
+            })
+            .collect::<Vec<_>>();
+
+        let victim = if wanted {
+            // Prefer evicting inbound peers, then the most recently connected ones.
+            evictable
+                .iter()
+                .max_by_key(|state| {
+                    (
+                        state.info.direction == ConnectionDirection::Inbound,
+                        state.info.connected_at,
+                    )
+                })
+                .map(|state| state.info.id)
+        } else {
+            let candidates = evictable
+                .iter()
+                .map(|state| state.eviction_candidate(rules.reputation, rules.static_peers))
+                .collect::<Vec<_>>();
+            rules.eviction_policy.select(newcomer, &candidates)
+        };
+
+        match victim.and_then(|victim| evictable.into_iter().find(|state| state.info.id == victim))
+        {
+            Some(victim) => Slot::Evict(victim.info.id, victim.commands.clone()),
+            None => Slot::Full(res.err()),
+        }
+    }
 
-        self.mapping.remove(&remote_id).is_some()
+    fn ips(&self) -> impl Iterator<Item = IpAddr> + '_ {
+        self.mapping.values().map(|state| state.addr().ip())
+    }
+
+    /// Remove the session, unless it has already been replaced by another connection.
+    fn disconnect_peer(&mut self, remote_id: PeerId, connection_id: Uuid) -> bool {
+        match self.mapping.entry(remote_id) {
+            Entry::Occupied(entry) if matches!(entry.get(), PeerState::Connected(state) if state.connection_id == connection_id) =>
+            {
+                debug!("disconnecting peer {}", remote_id);
+                entry.remove();
+                true
+            }
+            _ => false,
+        }
     }
 }
 
@@ -93,27 +532,45 @@ impl Default for PeerStreams {
 #[derive(Educe)]
 #[educe(Clone)]
 struct PeerStreamHandshakeData<C> {
-    port: u16,
+    endpoints: Arc<Endpoints>,
     protocol_version: ProtocolVersion,
     secret_key: SecretKey,
     client_version: String,
     capabilities: Arc<CapabilitySet>,
     capability_server: Arc<C>,
+    events: broadcast::Sender<SwarmEvent>,
+    ban_list: Arc<BanList>,
+    reputation: Arc<Reputation>,
+    trusted_peers: Arc<RwLock<HashSet<PeerId>>>,
+    network_policy: Arc<RwLock<NetworkPolicy>>,
+    peer_limits: Arc<RwLock<PeerLimits>>,
+    reservations: Arc<RwLock<Vec<CapabilityReservation>>>,
+    eviction_policy: Arc<dyn EvictionPolicy>,
+    static_peers: Arc<StaticPeers>,
+    known_peers: Arc<KnownPeers>,
+    draining: Arc<AtomicBool>,
+    config: Arc<RwLock<SwarmConfig>>,
 }
 
 async fn handle_incoming<C>(
     task_group: Weak<TaskGroup>,
     streams: Arc<Mutex<PeerStreams>>,
-    node_filter: Arc<Mutex<dyn NodeFilter>>,
+    node_filter: Arc<dyn NodeFilter>,
     tcp_incoming: TcpListener,
     cidr: Option<IpCidr>,
+    mut shutdown: watch::Receiver<bool>,
     handshake_data: PeerStreamHandshakeData<C>,
 ) where
     C: CapabilityServer,
 {
     let _: anyhow::Result<()> = async {
         loop {
-            match tcp_incoming.accept().await {
+            let accepted = tokio::select! {
+                // Swarm is shut down, close the listener.
+                _ = shutdown.changed() => return Ok(()),
+                accepted = tcp_incoming.accept() => accepted,
+            };
+            match accepted {
                 Err(e) => {
                     bail!("failed to accept peer: {:?}, shutting down", e);
                 }
@@ -122,6 +579,12 @@ async fn handle_incoming<C>(
                         .upgrade()
                         .ok_or_else(|| anyhow!("task group is down"))?;
 
+                    if handshake_data.draining.load(Ordering::Relaxed) {
+                        trace!("Ignoring connection request from {}: draining", remote_addr);
+
+                        continue;
+                    }
+
                     if let Some(cidr) = &cidr {
                         if !cidr.contains(&remote_addr.ip()) {
                             debug!(
@@ -133,10 +596,37 @@ async fn handle_incoming<C>(
                         }
                     }
 
+                    if let Some(ban) = handshake_data.ban_list.find(None, Some(remote_addr.ip())) {
+                        debug!(
+                            "Ignoring connection request: {} is banned ({})",
+                            remote_addr, ban.reason
+                        );
+
+                        continue;
+                    }
+
+                    // Streams are locked before the policy everywhere else, so do not hold both here.
+                    let peer_ips = streams.lock().ips().collect::<Vec<_>>();
+                    let policy_res = {
+                        let policy = handshake_data.network_policy.read();
+                        policy
+                            .check_addr(remote_addr.ip())
+                            .and_then(|_| policy.check_subnets(remote_addr.ip(), peer_ips))
+                    };
+                    if let Err(violation) = policy_res {
+                        debug!(
+                            "Ignoring connection request from {}: {}",
+                            remote_addr, violation
+                        );
+
+                        continue;
+                    }
+
                     let f = handle_incoming_request(
                         streams.clone(),
                         node_filter.clone(),
                         stream,
+                        remote_addr,
                         handshake_data.clone(),
                     );
                     tasks.spawn_with_name(format!("Incoming connection setup: {}", remote_addr), f);
@@ -147,28 +637,156 @@ async fn handle_incoming<C>(
     .await;
 }
 
+/// Bind a listener socket. IPv6 sockets are made IPv6-only so that they can share a port with an IPv4 listener.
+fn bind_listener(addr: SocketAddr) -> anyhow::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+
+    Ok(TcpListener::from_std(socket.into())?)
+}
+
+/// Externally visible addresses, at most one per address family.
+#[derive(Clone, Copy, Debug, Default)]
+struct ExternalAddrs {
+    v4: Option<SocketAddr>,
+    v6: Option<SocketAddr>,
+}
+
+impl ExternalAddrs {
+    fn set(&mut self, addr: SocketAddr) {
+        if addr.is_ipv4() {
+            self.v4 = Some(addr);
+        } else {
+            self.v6 = Some(addr);
+        }
+    }
+
+    fn get(&self, ipv4: bool) -> Option<SocketAddr> {
+        if ipv4 {
+            self.v4
+        } else {
+            self.v6
+        }
+    }
+
+    fn to_vec(self) -> Vec<SocketAddr> {
+        self.v4.into_iter().chain(self.v6).collect()
+    }
+}
+
+/// Addresses we listen on, plus the externally visible addresses to advertise instead of them.
+#[derive(Debug, Default)]
+struct Endpoints {
+    listen_addrs: Vec<SocketAddr>,
+    external_addrs: RwLock<ExternalAddrs>,
+}
+
+impl Endpoints {
+    /// Addresses under which we can be reached.
+    /// External addresses take the place of listeners of the same address family.
+    fn advertised_addrs(&self) -> Vec<SocketAddr> {
+        let external_addrs = *self.external_addrs.read();
+        external_addrs
+            .to_vec()
+            .into_iter()
+            .chain(
+                self.listen_addrs
+                    .iter()
+                    .copied()
+                    .filter(|addr| external_addrs.get(addr.is_ipv4()).is_none()),
+            )
+            .collect()
+    }
+
+    /// Port to announce in Hello to a peer at `remote_addr`.
+    /// Prefers an address of the same family, since that is how the peer would reach us back.
+    fn hello_port(&self, remote_addr: SocketAddr) -> u16 {
+        let addrs = self.advertised_addrs();
+        addrs
+            .iter()
+            .find(|addr| addr.is_ipv4() == remote_addr.is_ipv4())
+            .or_else(|| addrs.first())
+            .map_or(0, |addr| addr.port())
+    }
+}
+
+/// Outbound dial whose outcome is settled by the first message of the session,
+/// since a remote without free slots still completes Hello before disconnecting.
+struct PendingDial {
+    dial_history: Arc<DialHistory>,
+    known_peers: Arc<KnownPeers>,
+    record: NodeRecord,
+}
+
+impl PendingDial {
+    fn settle(self, disconnected: Option<DisconnectReason>) {
+        let Self {
+            dial_history,
+            known_peers,
+            record,
+        } = self;
+        match disconnected {
+            Some(reason) => dial_history.record(
+                record.id,
+                record.addr,
+                DialOutcome::Failed(DialError::Disconnected(reason)),
+            ),
+            None => {
+                dial_history.record(record.id, record.addr, DialOutcome::Success);
+                known_peers.record_success(record);
+            }
+        }
+    }
+}
+
 /// Set up newly connected peer's state, start its tasks
+#[allow(clippy::too_many_arguments)]
 fn setup_peer_state<C, Io>(
     streams: Weak<Mutex<PeerStreams>>,
     capability_server: Arc<C>,
-    remote_id: PeerId,
+    events: broadcast::Sender<SwarmEvent>,
+    reputation: Arc<Reputation>,
+    config: Arc<RwLock<SwarmConfig>>,
+    addr: SocketAddr,
+    direction: ConnectionDirection,
+    mut pending_dial: Option<PendingDial>,
     peer: PeerStream<Io>,
 ) -> ConnectedPeerState
 where
     C: CapabilityServer,
     Io: Transport,
 {
+    let remote_id = peer.remote_id();
     let capability_set = peer
         .capabilities()
         .iter()
         .copied()
         .map(|cap_info| (cap_info.name, cap_info.version))
         .collect::<HashMap<_, _>>();
+    let info = PeerInfo {
+        id: remote_id,
+        addr,
+        direction,
+        connected_at: Instant::now(),
+        capabilities: peer.capabilities().to_vec(),
+        client_version: peer.remote_client_version().to_string(),
+    };
     let (mut sink, mut stream) = futures::StreamExt::split(peer);
     let (peer_disconnect_tx, mut peer_disconnect_rx) = unbounded_channel();
+    let (commands_tx, mut commands) = channel(PEER_COMMAND_CHANNEL_CAPACITY);
+    let counters = Arc::new(PeerCounters::default());
     let tasks = TaskGroup::default();
+    let connection_id = Uuid::new_v4();
 
     capability_server.on_peer_connect(remote_id, capability_set);
+    let _ = events.send(SwarmEvent::PeerConnected(info.clone()));
 
     let pinged = Arc::new(AtomicBool::default());
     let (pings_tx, mut pings) = channel(1);
@@ -178,19 +796,32 @@ where
         let peer_disconnect_tx = peer_disconnect_tx.clone();
         let capability_server = capability_server.clone();
         let pinged = pinged.clone();
+        let counters = counters.clone();
+        let reputation = reputation.clone();
         async move {
             let disconnect_signal = {
                 async move {
                     while let Some(message) = stream.next().await {
+                        if let Some(dial) = pending_dial.take() {
+                            dial.settle(match &message {
+                                Ok(PeerMessage::Disconnect(reason)) => Some(*reason),
+                                Err(_) => Some(DisconnectReason::TcpSubsystemError),
+                                Ok(_) => None,
+                            });
+                        }
                         match message {
                             Err(e) => {
                                 debug!("Peer incoming error: {}", e);
+                                if is_protocol_breach(&e) {
+                                    reputation.report(remote_id, ReputationEvent::ProtocolBreach);
+                                }
                                 break;
                             }
                             Ok(PeerMessage::Subprotocol(SubprotocolMessage {
                                 cap_name,
                                 message,
                             })) => {
+                                counters.record_in(&message);
                                 // Actually handle the message
                                 capability_server
                                     .on_peer_event(
@@ -219,6 +850,9 @@ where
                     }
 
                     // Ingress stream is closed, force disconnect the peer.
+                    if let Some(dial) = pending_dial {
+                        dial.settle(Some(DisconnectReason::TcpSubsystemError));
+                    }
                     DisconnectSignal {
                         initiator: DisconnectInitiator::Remote,
                         reason: DisconnectReason::DisconnectRequested,
@@ -232,6 +866,9 @@ where
         .instrument(span!(Level::DEBUG, "IN", "peer={}", remote_id.to_string(),))
     });
 
+    let egress_counters = counters.clone();
+    let egress_config = config.clone();
+    let egress_capabilities = info.capabilities.clone();
     tasks.spawn_with_name(
         format!("peer {} egress router & disconnector", remote_id),
         async move {
@@ -263,6 +900,22 @@ where
                             }
                         };
                     },
+                    // Command from peer handle.
+                    Some(command) = commands.recv() => {
+                        match command {
+                            PeerCommand::Message { capability_name, message } => {
+                                egress = Some(PeerMessage::Subprotocol(SubprotocolMessage {
+                                    cap_name: capability_name, message
+                                }));
+                            }
+                            PeerCommand::Disconnect(reason) => {
+                                egress = Some(PeerMessage::Disconnect(reason));
+                                disconnecting = Some(DisconnectSignal {
+                                    initiator: DisconnectInitiator::Local, reason
+                                });
+                            }
+                        }
+                    }
                     // We ping the peer.
                     Some(tx) = pings.recv() => {
                         egress = Some(PeerMessage::Ping);
@@ -284,6 +937,16 @@ where
                 if let Some(message) = egress {
                     trace!("Sending message: {:?}", message);
 
+                    // Messages of capabilities the peer does not share are dropped by the stream.
+                    let counted = match &message {
+                        PeerMessage::Subprotocol(SubprotocolMessage { cap_name, message })
+                            if is_shared(&egress_capabilities, *cap_name, message.id) =>
+                        {
+                            Some(message.clone())
+                        }
+                        _ => None,
+                    };
+
                     // Send egress message, force disconnect on error.
                     if let Err(e) = sink.send(message).await {
                         debug!("peer disconnected with error {:?}", e);
@@ -291,15 +954,21 @@ where
                             initiator: DisconnectInitiator::LocalForceful,
                             reason: DisconnectReason::TcpSubsystemError,
                         });
-                    } else if let Some(trigger) = trigger {
-                        let _ = trigger.send(());
+                    } else {
+                        if let Some(message) = counted {
+                            egress_counters.record_out(&message);
+                        }
+                        if let Some(trigger) = trigger {
+                            let _ = trigger.send(());
+                        }
                     }
                 }
 
                 if let Some(DisconnectSignal { initiator, reason }) = disconnecting {
                     if let DisconnectInitiator::Local = initiator {
                         // We have sent disconnect message, wait for grace period.
-                        sleep(Duration::from_secs(GRACE_PERIOD_SECS)).await;
+                        let grace_period = egress_config.read().disconnect_grace_period;
+                        sleep(grace_period).await;
                     }
                     capability_server
                         .on_peer_event(
@@ -309,6 +978,11 @@ where
                             },
                         )
                         .await;
+                    let _ = events.send(SwarmEvent::PeerDisconnected {
+                        id: remote_id,
+                        initiator,
+                        reason,
+                    });
                     break;
                 }
             }
@@ -317,7 +991,7 @@ where
             if let Some(streams) = streams.upgrade() {
                 // This is the last line that is guaranteed to be executed.
                 // After this the peer's task group is dropped and any alive tasks are forcibly cancelled.
-                streams.lock().disconnect_peer(remote_id);
+                streams.lock().disconnect_peer(remote_id, connection_id);
             }
         }
         .instrument(span!(
@@ -334,9 +1008,14 @@ where
 
             let (tx, rx) = oneshot();
             if pings_tx.send(tx).await.is_ok() && rx.await.is_ok() {
-                sleep(PING_TIMEOUT).await;
+                let (ping_interval, pong_timeout) = {
+                    let config = config.read();
+                    (config.ping_interval, config.pong_timeout)
+                };
+                sleep(pong_timeout).await;
 
                 if pinged.load(Ordering::Relaxed) {
+                    reputation.report(remote_id, ReputationEvent::Timeout);
                     let _ = peer_disconnect_tx.send(DisconnectSignal {
                         initiator: DisconnectInitiator::Local,
                         reason: DisconnectReason::PingTimeout,
@@ -345,20 +1024,60 @@ where
                     return;
                 }
 
+                sleep(ping_interval.saturating_sub(pong_timeout)).await;
                 continue;
             }
 
             return;
         }
     });
-    ConnectedPeerState { tasks }
+    ConnectedPeerState {
+        connection_id,
+        tasks,
+        info,
+        counters,
+        commands: commands_tx,
+    }
+}
+
+/// Whether a stream error is caused by the peer misbehaving rather than by the connection failing.
+fn is_protocol_breach(e: &io::Error) -> bool {
+    ProtocolBreach::is_cause_of(e)
+}
+
+/// Decision on an incoming connection that completed handshake.
+enum Admission {
+    Accept,
+    /// Accept in place of an existing session.
+    Replace(ConnectedPeerState),
+    /// Accept after disconnecting another peer to make room.
+    Evict(Sender<PeerCommand>),
+    /// Reject with the given reason.
+    Reject(DisconnectReason),
+}
+
+/// Tells the peer why it is being rejected and waits a bit for it to close the connection.
+async fn reject_peer<Io: Transport>(
+    mut peer: PeerStream<Io>,
+    reason: DisconnectReason,
+    grace_period: Duration,
+) {
+    debug!("Rejecting peer {}: {}", peer.remote_id(), reason);
+    if peer.send(PeerMessage::Disconnect(reason)).await.is_err() {
+        return;
+    }
+    let _ = tokio::time::timeout(grace_period, async {
+        while let Some(Ok(_)) = peer.next().await {}
+    })
+    .await;
 }
 
 /// Establishes the connection with peer and adds them to internal state.
 async fn handle_incoming_request<C, Io>(
     streams: Arc<Mutex<PeerStreams>>,
-    node_filter: Arc<Mutex<dyn NodeFilter>>,
+    node_filter: Arc<dyn NodeFilter>,
     stream: Io,
+    remote_addr: SocketAddr,
     handshake_data: PeerStreamHandshakeData<C>,
 ) where
     C: CapabilityServer,
@@ -370,61 +1089,265 @@ async fn handle_incoming_request<C, Io>(
         client_version,
         capabilities,
         capability_server,
-        port,
+        endpoints,
+        events,
+        ban_list,
+        reputation,
+        trusted_peers,
+        peer_limits,
+        reservations,
+        eviction_policy,
+        static_peers,
+        known_peers,
+        draining,
+        config,
+        ..
     } = handshake_data;
     // Do handshake and convert incoming connection into stream.
-    let peer_res = tokio::time::timeout(
-        Duration::from_secs(HANDSHAKE_TIMEOUT_SECS),
-        PeerStream::incoming(
-            stream,
-            secret_key,
-            protocol_version,
-            client_version,
-            capabilities.get_capabilities().to_vec(),
-            port,
-        ),
-    )
-    .await
-    .unwrap_or_else(|_| Err(anyhow!("incoming connection timeout")));
+    let (ecies_timeout, hello_timeout) = {
+        let config = config.read();
+        (config.ecies_timeout, config.hello_timeout)
+    };
+    let peer_res = async {
+        let transport =
+            tokio::time::timeout(ecies_timeout, ECIESStream::incoming(stream, secret_key))
+                .await
+                .map_err(|_| HandshakeError::Timeout("ECIES handshake"))?
+                .map_err(HandshakeError::Ecies)?;
+        tokio::time::timeout(
+            hello_timeout,
+            PeerStream::new(
+                transport,
+                secret_key,
+                protocol_version,
+                client_version,
+                capabilities.get_capabilities().to_vec(),
+                endpoints.hello_port(remote_addr),
+            ),
+        )
+        .await
+        .map_err(|_| HandshakeError::Timeout("Hello"))?
+        .map_err(HandshakeError::from_hello)
+    }
+    .await;
 
     match peer_res {
         Ok(peer) => {
             let remote_id = peer.remote_id();
-            let s = streams.clone();
-            let mut s = s.lock();
-            let node_filter = node_filter.clone();
-            let PeerStreams { mapping } = &mut *s;
-            let total_connections = mapping.len();
-
-            match mapping.entry(remote_id) {
-                Entry::Occupied(entry) => {
-                    debug!(
-                        "We are already {} to remote peer {}!",
-                        if entry.get().is_connected() {
-                            "connected"
-                        } else {
-                            "connecting"
+            let local_id = pk2id(&PublicKey::from_secret_key(SECP256K1, &secret_key));
+            let trusted = trusted_peers.read().contains(&remote_id);
+
+            // Reject early without consulting the node filter, admission below checks again after it.
+            let rejection = match streams.lock().mapping.get(&remote_id) {
+                _ if draining.load(Ordering::Relaxed) => Some(DisconnectReason::ClientQuitting),
+                Some(state)
+                    if !(remote_id > local_id
+                        && state.direction() == ConnectionDirection::Outbound) =>
+                {
+                    Some(DisconnectReason::AlreadyConnected)
+                }
+                None if ban_list.find(Some(remote_id), None).is_some() => {
+                    Some(DisconnectReason::UselessPeer)
+                }
+                _ => None,
+            };
+            if let Some(reason) = rejection {
+                let grace_period = config.read().reject_grace_period;
+                reject_peer(peer, reason, grace_period).await;
+                return;
+            }
+
+            let request = NodeFilterRequest {
+                id: remote_id,
+                addr: remote_addr,
+                direction: ConnectionDirection::Inbound,
+                counts: streams.lock().counts(),
+                reputation: reputation.score(remote_id),
+            };
+            let filtered = !trusted && !node_filter.allow(request).await;
+            let placeholder_id = Uuid::new_v4();
+            let placeholder = PeerState::Connecting {
+                connection_id: placeholder_id,
+                info: ConnectingPeerInfo {
+                    id: remote_id,
+                    addr: remote_addr,
+                    direction: ConnectionDirection::Inbound,
+                    started_at: Instant::now(),
+                },
+            };
+
+            let admission = {
+                let mut s = streams.lock();
+                let slot = if trusted {
+                    Slot::Free
+                } else {
+                    s.slot(
+                        &EvictionCandidate {
+                            id: remote_id,
+                            addr: remote_addr,
+                            direction: ConnectionDirection::Inbound,
+                            connected_at: Instant::now(),
+                            capabilities: peer.capabilities().to_vec(),
+                            reputation: reputation.score(remote_id),
+                            is_static: static_peers.contains(remote_id),
                         },
-                        remote_id
-                    );
+                        &SlotRules {
+                            limits: *peer_limits.read(),
+                            reservations: &reservations.read(),
+                            eviction_policy: &*eviction_policy,
+                            trusted_peers: &trusted_peers.read(),
+                            static_peers: &static_peers,
+                            reputation: &reputation,
+                        },
+                    )
+                };
+
+                match s.mapping.entry(remote_id) {
+                    _ if draining.load(Ordering::Relaxed) => {
+                        debug!("Rejecting peer {}: draining", remote_id);
+                        Admission::Reject(DisconnectReason::ClientQuitting)
+                    }
+                    // Both sides of simultaneous connections keep the one dialed by the node with greater ID.
+                    Entry::Occupied(mut entry)
+                        if remote_id > local_id
+                            && entry.get().direction() == ConnectionDirection::Outbound =>
+                    {
+                        debug!(
+                            "Simultaneous connection with {}, replacing outbound with inbound",
+                            remote_id
+                        );
+                        match entry.insert(placeholder) {
+                            PeerState::Connected(old) => Admission::Replace(old),
+                            PeerState::Connecting { .. } => Admission::Accept,
+                        }
+                    }
+                    Entry::Occupied(entry) => {
+                        debug!(
+                            "We are already {} to remote peer {}!",
+                            if entry.get().is_connected() {
+                                "connected"
+                            } else {
+                                "connecting"
+                            },
+                            remote_id
+                        );
+                        Admission::Reject(DisconnectReason::AlreadyConnected)
+                    }
+                    Entry::Vacant(_) if ban_list.find(Some(remote_id), None).is_some() => {
+                        debug!("Rejecting banned peer {}", remote_id);
+                        Admission::Reject(DisconnectReason::UselessPeer)
+                    }
+                    Entry::Vacant(_) if filtered => {
+                        trace!("Node filter rejected peer {}, disconnecting", remote_id);
+                        let _ = events.send(SwarmEvent::NodeFilterRejected {
+                            id: remote_id,
+                            addr: remote_addr,
+                            direction: ConnectionDirection::Inbound,
+                        });
+                        Admission::Reject(DisconnectReason::UselessPeer)
+                    }
+                    Entry::Vacant(entry) => match slot {
+                        Slot::Free => {
+                            entry.insert(placeholder);
+                            Admission::Accept
+                        }
+                        Slot::Evict(victim, commands) => {
+                            debug!("Evicting peer {} to make room for {}", victim, remote_id);
+                            entry.insert(placeholder);
+                            Admission::Evict(commands)
+                        }
+                        Slot::Full(violation) => {
+                            trace!(
+                                "No slot for peer {}: {}",
+                                remote_id,
+                                violation.map_or_else(
+                                    || "too many peers".to_string(),
+                                    |v| v.to_string()
+                                )
+                            );
+                            Admission::Reject(DisconnectReason::TooManyPeers)
+                        }
+                    },
+                }
+            };
+
+            match admission {
+                Admission::Accept => {}
+                Admission::Replace(old) => {
+                    // Let the old session deliver its disconnect before the new one is reported as connected.
+                    let commands = old.commands.clone();
+                    let _ = commands
+                        .send(PeerCommand::Disconnect(DisconnectReason::AlreadyConnected))
+                        .await;
+                    let deadline = config.read().disconnect_deadline();
+                    let _ = tokio::time::timeout(deadline, commands.closed()).await;
+                }
+                Admission::Evict(commands) => {
+                    let _ = commands
+                        .send(PeerCommand::Disconnect(DisconnectReason::TooManyPeers))
+                        .await;
+                }
+                Admission::Reject(reason) => {
+                    let grace_period = config.read().reject_grace_period;
+                    reject_peer(peer, reason, grace_period).await;
+                    return;
                 }
-                Entry::Vacant(entry) => {
-                    if node_filter.lock().allow(total_connections, remote_id) {
+            }
+
+            let is_placeholder = |state: &PeerState| matches!(state, PeerState::Connecting { connection_id, .. } if *connection_id == placeholder_id);
+            let quitting = {
+                let mut s = streams.lock();
+                match s.mapping.entry(remote_id) {
+                    // Swarm started draining during the handshake.
+                    Entry::Occupied(entry)
+                        if is_placeholder(entry.get()) && draining.load(Ordering::Relaxed) =>
+                    {
+                        debug!("Rejecting peer {}: draining", remote_id);
+                        entry.remove();
+                        Some(peer)
+                    }
+                    Entry::Occupied(mut entry) if is_placeholder(entry.get()) => {
                         debug!("New incoming peer connected: {}", remote_id);
+                        // Remember where the peer can be reached back, if it accepts connections.
+                        if peer.remote_port() != 0 {
+                            known_peers.record_success(NodeRecord {
+                                id: remote_id,
+                                addr: SocketAddr::new(remote_addr.ip(), peer.remote_port()),
+                            });
+                        }
                         entry.insert(PeerState::Connected(setup_peer_state(
                             Arc::downgrade(&streams),
                             capability_server,
-                            remote_id,
+                            events,
+                            reputation,
+                            config.clone(),
+                            remote_addr,
+                            ConnectionDirection::Inbound,
+                            None,
                             peer,
                         )));
-                    } else {
-                        trace!("Node filter rejected peer {}, disconnecting", remote_id);
+                        None
+                    }
+                    _ => {
+                        debug!("Incoming peer {} was dropped while connecting", remote_id);
+                        None
                     }
                 }
+            };
+
+            if let Some(peer) = quitting {
+                let grace_period = config.read().reject_grace_period;
+                reject_peer(peer, DisconnectReason::ClientQuitting, grace_period).await;
             }
         }
         Err(e) => {
             debug!("Peer disconnected with error {}", e);
+            let _ = events.send(SwarmEvent::HandshakeFailed {
+                id: None,
+                addr: remote_addr,
+                direction: ConnectionDirection::Inbound,
+                error: Arc::new(e),
+            });
         }
     }
 }
@@ -481,7 +1404,10 @@ pub struct Swarm<C: CapabilityServer> {
 
     currently_connecting: Arc<AtomicUsize>,
 
-    node_filter: Arc<Mutex<dyn NodeFilter>>,
+    node_filter: Arc<dyn NodeFilter>,
+    peer_limits: Arc<RwLock<PeerLimits>>,
+    reservations: Arc<RwLock<Vec<CapabilityReservation>>>,
+    eviction_policy: Arc<dyn EvictionPolicy>,
 
     capabilities: Arc<CapabilitySet>,
     #[educe(Debug(ignore))]
@@ -491,7 +1417,27 @@ pub struct Swarm<C: CapabilityServer> {
     secret_key: SecretKey,
     protocol_version: ProtocolVersion,
     client_version: String,
-    port: u16,
+    endpoints: Arc<Endpoints>,
+    proxy: Option<ProxyConfig>,
+
+    events: broadcast::Sender<SwarmEvent>,
+    ban_list: Arc<BanList>,
+    reputation: Arc<Reputation>,
+    static_peers: Arc<StaticPeers>,
+    trusted_peers: Arc<RwLock<HashSet<PeerId>>>,
+    network_policy: Arc<RwLock<NetworkPolicy>>,
+    dial_history: Arc<DialHistory>,
+    known_peers: Arc<KnownPeers>,
+    /// Nodes waiting to be dialed and dials in progress.
+    dial_scheduler: Mutex<DialScheduler>,
+    discovery: Arc<DiscoverySources>,
+    /// Nodes found by discovery, to the dialer. `None` if dialing is disabled.
+    discovered: Option<Sender<(String, NodeRecord)>>,
+    /// No new connections are accepted or dialed.
+    draining: Arc<AtomicBool>,
+    /// Set once by `shutdown`, stops listeners and the dialer.
+    shutdown: watch::Sender<bool>,
+    config: Arc<RwLock<SwarmConfig>>,
 }
 
 /// Builder for ergonomically creating a new `Server`.
@@ -499,7 +1445,22 @@ pub struct Swarm<C: CapabilityServer> {
 pub struct SwarmBuilder {
     task_group: Option<Arc<TaskGroup>>,
     listen_options: Option<ListenOptions>,
+    dial_options: Option<DialOptions>,
+    max_peers: Option<usize>,
     client_version: String,
+    external_addrs: ExternalAddrs,
+    proxy: Option<ProxyConfig>,
+    ban_list_path: Option<PathBuf>,
+    reputation_config: ReputationConfig,
+    static_peers: Vec<NodeRecord>,
+    trusted_peers: Vec<PeerId>,
+    network_policy: NetworkPolicy,
+    peer_store: Option<Box<dyn PeerStore>>,
+    node_filter: Option<Box<dyn NodeFilter>>,
+    reservations: Vec<CapabilityReservation>,
+    eviction_policy: Option<Box<dyn EvictionPolicy>>,
+    outbound_rotation: Option<OutboundRotation>,
+    config: SwarmConfig,
 }
 
 impl SwarmBuilder {
@@ -508,43 +1469,156 @@ impl SwarmBuilder {
         self
     }
 
+    /// Accept incoming connections. Without listen options the swarm only dials out.
     pub fn with_listen_options(mut self, options: ListenOptions) -> Self {
         self.listen_options = Some(options);
         self
     }
 
+    /// Dial nodes from discovery and the peer store. Without dial options the swarm only accepts incoming
+    /// connections, static peers and peers added explicitly.
+    pub fn with_dial_options(mut self, options: DialOptions) -> Self {
+        self.dial_options = Some(options);
+        self
+    }
+
+    /// Limit on inbound and outbound peers together. Defaults to the sum of `max_inbound` and `max_outbound`.
+    pub fn with_max_peers(mut self, max_peers: usize) -> Self {
+        self.max_peers = Some(max_peers);
+        self
+    }
+
     pub fn with_client_version(mut self, version: String) -> Self {
         self.client_version = version;
         self
     }
 
-    /// Create a new RLPx node
+    /// Advertise this address to peers instead of the listen addresses of its family, e.g. when behind NAT.
+    /// A dual-stack node may set one IPv4 and one IPv6 address; a later address replaces one of the same family.
+    pub fn with_external_addr(mut self, addr: SocketAddr) -> Self {
+        self.external_addrs.set(addr);
+        self
+    }
+
+    /// Load ban list from this file on startup and persist it there on every change.
+    pub fn with_ban_list_path(mut self, path: PathBuf) -> Self {
+        self.ban_list_path = Some(path);
+        self
+    }
+
+    /// Tune peer reputation scoring.
+    pub fn with_reputation_config(mut self, config: ReputationConfig) -> Self {
+        self.reputation_config = config;
+        self
+    }
+
+    /// Keep connections to these peers, redialing them after disconnects.
+    pub fn with_static_peers(mut self, peers: Vec<NodeRecord>) -> Self {
+        self.static_peers = peers;
+        self
+    }
+
+    /// Accept these peers regardless of peer limits and node filter.
+    pub fn with_trusted_peers(mut self, peers: Vec<PeerId>) -> Self {
+        self.trusted_peers = peers;
+        self
+    }
+
+    /// Restrict addresses of inbound and outbound connections.
+    pub fn with_network_policy(mut self, policy: NetworkPolicy) -> Self {
+        self.network_policy = policy;
+        self
+    }
+
+    /// Remember nodes we have connected to in this store, and dial them first on startup.
+    pub fn with_peer_store(mut self, store: Box<dyn PeerStore>) -> Self {
+        self.peer_store = Some(store);
+        self
+    }
+
+    /// Consult this filter before admitting peers, in addition to peer limits.
+    pub fn with_node_filter(mut self, filter: Box<dyn NodeFilter>) -> Self {
+        self.node_filter = Some(filter);
+        self
+    }
+
+    /// Keep peer slots for peers sharing the given capabilities.
+    pub fn with_capability_reservations(
+        mut self,
+        reservations: Vec<CapabilityReservation>,
+    ) -> Self {
+        self.reservations = reservations;
+        self
+    }
+
+    /// Choose peers to disconnect when the pool is full and a new peer asks for a slot.
+    /// `DefaultEvictionPolicy` is used unless set, pass `Box::new(())` to never evict.
+    pub fn with_eviction_policy(mut self, policy: Box<dyn EvictionPolicy>) -> Self {
+        self.eviction_policy = Some(policy);
+        self
+    }
+
+    /// Periodically replace outbound peers once the pool is full. Validated on build.
+    pub fn with_outbound_rotation(mut self, rotation: OutboundRotation) -> Self {
+        self.outbound_rotation = Some(rotation);
+        self
+    }
+
+    /// Timeouts and intervals, validated on build.
+    pub fn with_config(mut self, config: SwarmConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Tunnel all outbound connections through the given proxy.
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Create a new RLPx node
     pub async fn build<C: CapabilityServer>(
         self,
         capability_mask: BTreeMap<CapabilityId, CapabilityLength>,
         capability_server: Arc<C>,
         secret_key: SecretKey,
     ) -> anyhow::Result<Arc<Swarm<C>>> {
-        Swarm::new_inner(
-            secret_key,
-            self.client_version,
-            self.task_group,
-            capability_mask.into(),
-            capability_server,
-            self.listen_options,
-        )
-        .await
+        Swarm::new_inner(self, secret_key, capability_mask.into(), capability_server).await
     }
 }
 
+/// Address to accept incoming connections on.
+#[derive(Clone, Debug)]
+pub struct ListenAddr {
+    pub addr: SocketAddr,
+    /// Only accept connections from this range.
+    pub cidr: Option<IpCidr>,
+}
+
+impl From<SocketAddr> for ListenAddr {
+    fn from(addr: SocketAddr) -> Self {
+        Self { addr, cidr: None }
+    }
+}
+
+/// Inbound side of a `Swarm`.
+#[derive(Clone, Debug)]
+pub struct ListenOptions {
+    pub listen_addrs: Vec<ListenAddr>,
+    /// Limit on inbound peers, so that they cannot crowd out the peers we dial ourselves.
+    pub max_inbound: usize,
+}
+
+/// Outbound side of a `Swarm`. Dial scheduling is tuned with `SwarmConfig`.
 #[derive(Educe)]
 #[educe(Debug)]
-pub struct ListenOptions {
+pub struct DialOptions {
+    /// Initial discovery sources, never restarted. See `Swarm::add_discovery` for more.
     #[educe(Debug(ignore))]
     pub discovery_tasks: StreamMap<String, Discovery>,
-    pub max_peers: usize,
-    pub addr: SocketAddr,
-    pub cidr: Option<IpCidr>,
+    /// Limit on outbound peers. Static and explicitly added peers take up outbound slots too, but are
+    /// dialed even when the limit is reached.
+    pub max_outbound: usize,
 }
 
 impl Swarm<()> {
@@ -552,7 +1626,22 @@ impl Swarm<()> {
         SwarmBuilder {
             task_group: None,
             listen_options: None,
+            dial_options: None,
+            max_peers: None,
             client_version: format!("rust-devp2p/{}", env!("CARGO_PKG_VERSION")),
+            external_addrs: ExternalAddrs::default(),
+            proxy: None,
+            ban_list_path: None,
+            reputation_config: ReputationConfig::default(),
+            static_peers: Vec::new(),
+            trusted_peers: Vec::new(),
+            network_policy: NetworkPolicy::default(),
+            peer_store: None,
+            node_filter: None,
+            reservations: Vec::new(),
+            eviction_policy: None,
+            outbound_rotation: None,
+            config: SwarmConfig::default(),
         }
     }
 }
@@ -569,48 +1658,131 @@ impl<C: CapabilityServer> Swarm<C> {
     }
 
     async fn new_inner(
+        builder: SwarmBuilder,
         secret_key: SecretKey,
-        client_version: String,
-        task_group: Option<Arc<TaskGroup>>,
         capabilities: CapabilitySet,
         capability_server: Arc<C>,
-        listen_options: Option<ListenOptions>,
     ) -> anyhow::Result<Arc<Self>> {
+        let SwarmBuilder {
+            task_group,
+            listen_options,
+            dial_options,
+            max_peers,
+            client_version,
+            external_addrs,
+            proxy,
+            ban_list_path,
+            reputation_config,
+            static_peers: static_peer_records,
+            trusted_peers,
+            network_policy,
+            peer_store,
+            node_filter,
+            reservations,
+            eviction_policy,
+            outbound_rotation,
+            config,
+        } = builder;
+
+        config.validate()?;
+        if let Some(rotation) = &outbound_rotation {
+            rotation.validate()?;
+        }
+        let config = Arc::new(RwLock::new(config));
+
         let tasks = task_group.unwrap_or_default();
 
         let protocol_version = ProtocolVersion::V5;
 
-        let port = listen_options
+        let streams = Arc::new(Mutex::new(PeerStreams::default()));
+        let node_filter: Arc<dyn NodeFilter> = node_filter.map_or(Arc::new(()), Arc::from);
+        let max_inbound = listen_options
+            .as_ref()
+            .map_or(0, |options| options.max_inbound);
+        let max_outbound = dial_options
             .as_ref()
-            .map_or(0, |options| options.addr.port());
+            .map_or(0, |options| options.max_outbound);
+        let peer_limits = Arc::new(RwLock::new(PeerLimits {
+            max_peers: max_peers.unwrap_or(max_inbound + max_outbound),
+            max_inbound,
+            max_outbound,
+        }));
+        let reservations = Arc::new(RwLock::new(reservations));
+        let eviction_policy: Arc<dyn EvictionPolicy> = match eviction_policy {
+            Some(policy) => Arc::from(policy),
+            None => Arc::new(DefaultEvictionPolicy::default()),
+        };
+
+        let ban_list = Arc::new(match ban_list_path {
+            Some(path) => BanList::load(path)?,
+            None => BanList::default(),
+        });
+        let reputation = Arc::new(Reputation::new(reputation_config, ban_list.clone()));
 
-        let streams = Arc::new(Mutex::new(PeerStreams::default()));
-        let node_filter = Arc::new(Mutex::new(MemoryNodeFilter::new(Arc::new(
-            listen_options
-                .as_ref()
-                .map_or(0.into(), |options| options.max_peers.into()),
-        ))));
+        let static_peers = Arc::new(StaticPeers::default());
+        for record in static_peer_records {
+            static_peers.insert(record);
+        }
+        let trusted_peers = Arc::new(RwLock::new(trusted_peers.into_iter().collect()));
+        let network_policy = Arc::new(RwLock::new(network_policy));
+        let known_peers = Arc::new(KnownPeers::load(peer_store)?);
+        let draining = Arc::new(AtomicBool::new(false));
+        let (shutdown, _) = watch::channel(false);
 
         let capabilities = Arc::new(capabilities);
+        let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let (discovered, discovered_rx) = match dial_options {
+            Some(_) => {
+                let (tx, rx) = channel(DISCOVERY_CHANNEL_CAPACITY);
+                (Some(tx), Some(rx))
+            }
+            None => (None, None),
+        };
+
+        let mut listeners = Vec::new();
+        for ListenAddr { addr, cidr } in listen_options
+            .into_iter()
+            .flat_map(|options| options.listen_addrs)
+        {
+            let tcp_incoming = bind_listener(addr)?;
+            let local_addr = tcp_incoming.local_addr()?;
+            listeners.push((tcp_incoming, local_addr, cidr));
+        }
+
+        let endpoints = Arc::new(Endpoints {
+            listen_addrs: listeners.iter().map(|&(_, addr, _)| addr).collect(),
+            external_addrs: RwLock::new(external_addrs),
+        });
 
-        if let Some(options) = &listen_options {
-            let tcp_incoming = TcpListener::bind(options.addr).await?;
-            let cidr = options.cidr.clone();
+        for (tcp_incoming, local_addr, cidr) in listeners {
             tasks.spawn_with_name(
-                "incoming handler",
+                format!("incoming handler: {}", local_addr),
                 handle_incoming(
                     Arc::downgrade(&tasks),
                     streams.clone(),
                     node_filter.clone(),
                     tcp_incoming,
                     cidr,
+                    shutdown.subscribe(),
                     PeerStreamHandshakeData {
-                        port,
+                        endpoints: endpoints.clone(),
                         protocol_version,
                         secret_key,
                         client_version: client_version.clone(),
                         capabilities: capabilities.clone(),
                         capability_server: capability_server.clone(),
+                        events: events.clone(),
+                        ban_list: ban_list.clone(),
+                        reputation: reputation.clone(),
+                        trusted_peers: trusted_peers.clone(),
+                        network_policy: network_policy.clone(),
+                        peer_limits: peer_limits.clone(),
+                        reservations: reservations.clone(),
+                        eviction_policy: eviction_policy.clone(),
+                        static_peers: static_peers.clone(),
+                        known_peers: known_peers.clone(),
+                        draining: draining.clone(),
+                        config: config.clone(),
                     },
                 ),
             );
@@ -621,72 +1793,234 @@ impl<C: CapabilityServer> Swarm<C> {
             streams,
             currently_connecting: Default::default(),
             node_filter,
+            peer_limits,
+            reservations,
+            eviction_policy,
             capabilities,
             capability_server,
             secret_key,
             protocol_version,
             client_version,
-            port,
+            endpoints,
+            proxy,
+            events,
+            ban_list,
+            reputation,
+            static_peers,
+            trusted_peers,
+            network_policy,
+            dial_history: Default::default(),
+            known_peers,
+            dial_scheduler: Default::default(),
+            discovery: Default::default(),
+            discovered,
+            draining,
+            shutdown,
+            config,
+        });
+
+        for peer in server.known_peers.peers() {
+            server.queue_dial(PEER_STORE_SOURCE, peer.record);
+        }
+
+        tasks.spawn_with_name("peer store flusher", {
+            let server = Arc::downgrade(&server);
+            async move {
+                loop {
+                    sleep(PEER_STORE_FLUSH_INTERVAL).await;
+
+                    match server.upgrade() {
+                        Some(server) => {
+                            if let Err(e) = server.known_peers.flush() {
+                                warn!("Failed to flush peer store: {}", e);
+                            }
+                        }
+                        None => return,
+                    }
+                }
+            }
+        });
+
+        tasks.spawn_with_name("static peer keeper", {
+            let server = Arc::downgrade(&server);
+            let tasks = Arc::downgrade(&tasks);
+            async move {
+                loop {
+                    let (server, tasks) = match (server.upgrade(), tasks.upgrade()) {
+                        (Some(server), Some(tasks)) if !server.is_shut_down() => (server, tasks),
+                        _ => return,
+                    };
+
+                    if server.is_draining() {
+                        drop(server);
+                        sleep(STATIC_DIAL_INTERVAL).await;
+                        continue;
+                    }
+
+                    let present = server
+                        .streams
+                        .lock()
+                        .mapping
+                        .keys()
+                        .copied()
+                        .collect::<HashSet<_>>();
+                    for NodeRecord { id, addr } in server
+                        .static_peers
+                        .due(Instant::now(), |id| present.contains(&id))
+                    {
+                        debug!("Dialing static peer {} at {}", id, addr);
+                        let counted = server.dial_scheduler.lock().start(id);
+                        tasks.spawn_with_name(format!("dial static peer {} at {}", id, addr), {
+                            let server = server.clone();
+                            async move {
+                                let connected = matches!(
+                                    server.add_peer_inner(addr, id, false).await,
+                                    Ok(true)
+                                );
+                                if counted {
+                                    server.dial_scheduler.lock().finish(id);
+                                }
+                                server
+                                    .static_peers
+                                    .dial_finished(id, connected, Instant::now());
+                            }
+                        });
+                    }
+
+                    drop(server);
+                    sleep(STATIC_DIAL_INTERVAL).await;
+                }
+            }
+            .instrument(span!(Level::DEBUG, "static peers"))
         });
 
-        if let Some(mut options) = listen_options {
+        if let (Some(options), Some(mut discovered)) = (dial_options, discovered_rx) {
+            let mut discovery_tasks = options.discovery_tasks;
+            for name in discovery_tasks.keys().cloned().collect::<Vec<_>>() {
+                let stream = Mutex::new(discovery_tasks.remove(&name));
+                server.add_discovery(
+                    name,
+                    move || {
+                        stream
+                            .lock()
+                            .take()
+                            .unwrap_or_else(|| Box::pin(futures::stream::empty()))
+                    },
+                    RestartPolicy::Never,
+                )?;
+            }
+
             tasks.spawn_with_name("dialer", {
                 let server = Arc::downgrade(&server);
                 let tasks = Arc::downgrade(&tasks);
                 async move {
-                    let current_peers = Arc::new(Mutex::new(HashSet::new()));
                     loop {
-                        if let Some(server) = server.upgrade() {
-                            let streams_len = server.streams.lock().mapping.len();
-                            let max_peers = server.node_filter.lock().max_peers();
-
-                            if streams_len < max_peers {
-                                trace!("Discovering peers as our peer count is too low: {} < {}", streams_len, max_peers);
-                                match tokio::time::timeout(
-                                    Duration::from_secs(DISCOVERY_TIMEOUT_SECS),
-                                    options.discovery_tasks.next(),
-                                )
-                                .await {
-                                    Err(_) => {
-                                        debug!("Failed to get new peer: timed out");
-                                    }
-                                    Ok(None) => {
-                                        debug!("Discoveries ended, dialer quitting");
-                                        return;
-                                    }
-                                    Ok(Some((disc_id, Ok(NodeRecord { addr, id: remote_id })))) => {
-                                        if let Some(tasks) = tasks.upgrade() {
-                                            if current_peers.lock().insert(remote_id) {
-                                                debug!("Discovered peer: {:?} ({})", remote_id, disc_id);
-                                                tasks.spawn_with_name(format!("add peer {} at {}", remote_id, addr), {
-                                                    let current_peers = current_peers.clone();
-                                                    async move {
-                                                        if tokio::time::timeout(
-                                                            Duration::from_secs(DISCOVERY_CONNECT_TIMEOUT_SECS),
-                                                            server.add_peer_inner(addr, remote_id, true)
-                                                        ).await.is_err() {
-                                                            debug!("Timed out adding peer {}", remote_id);
-                                                        }
-                                                        current_peers.lock().remove(&remote_id)
-                                                    }
-                                                });
-                                            }
-                                        }
-                                    }
-                                    Ok(Some((disc_id, Err(e)))) => warn!("Failed to get new peer: {} ({})", e, disc_id)
-                                }
+                        let server = match server.upgrade() {
+                            Some(server) if !server.is_shut_down() => server,
+                            _ => return,
+                        };
+                        let config = server.config();
+
+                        if server.is_draining() {
+                            trace!("Skipping discovery while draining");
+                            server.discovery.set_wanted(false);
+                            drop(server);
+                            sleep(Duration::from_secs(2)).await;
+                            continue;
+                        }
 
-                                sleep(DIAL_INTERVAL).await;
+                        while let Some(Some((source, record))) = discovered.recv().now_or_never() {
+                            trace!("Discovered peer: {:?} ({})", record.id, source);
+                            server.queue_dial(&source, record);
+                        }
+
+                        let limits = *server.peer_limits.read();
+                        let (counts, slots) = {
+                            let streams = server.streams.lock();
+                            let slots = streams.outbound_slots(&SlotRules {
+                                limits,
+                                reservations: &server.reservations.read(),
+                                eviction_policy: &*server.eviction_policy,
+                                trusted_peers: &server.trusted_peers.read(),
+                                static_peers: &server.static_peers,
+                                reputation: &server.reputation,
+                            });
+                            (streams.counts(), slots)
+                        };
+                        server.discovery.set_wanted(slots > 0);
+                        if slots == 0 {
+                            trace!("Skipping discovery as current number of peers is too high: {:?} >= {:?}", counts, limits);
+                            drop(server);
+                            sleep(Duration::from_secs(2)).await;
+                            continue;
+                        }
+
+                        // Dial up to twice the number of free slots, as some dials are bound to fail.
+                        let tasks = match tasks.upgrade() {
+                            Some(tasks) => tasks,
+                            None => return,
+                        };
+                        let in_flight = server.dial_scheduler.lock().in_flight();
+                        let budget = (slots * 2).min(config.max_concurrent_dials).saturating_sub(in_flight);
+                        for _ in 0..budget {
+                            let next = server.dial_scheduler.lock().pop();
+                            let (source, NodeRecord { addr, id: remote_id }) = match next {
+                                Some(next) => next,
+                                None => break,
+                            };
+
+                            if !server.reputation.allows_dial(remote_id) {
+                                trace!("Skipping peer {} with low reputation", remote_id);
+                            } else if let Some(backoff) = server.dial_history.backoff(remote_id) {
+                                trace!("Skipping peer {} in backoff for {:?}", remote_id, backoff);
                             } else {
-                                trace!("Skipping discovery as current number of peers is too high: {} >= {}", streams_len, max_peers);
-                                sleep(Duration::from_secs(2)).await;
+                                debug!("Dialing peer {} ({}) as our peer count is too low: {:?} < {:?}", remote_id, source, counts, limits);
+                                tasks.spawn_with_name(format!("add peer {} at {}", remote_id, addr), {
+                                    let server = server.clone();
+                                    async move {
+                                        let connected = matches!(server.add_peer_inner(addr, remote_id, true).await, Ok(true));
+                                        server.discovery.record_dial(&source, connected);
+                                        server.dial_scheduler.lock().finish(remote_id);
+                                    }
+                                });
+                                continue;
                             }
-                        } else {
-                            return;
+                            server.dial_scheduler.lock().finish(remote_id);
                         }
+
+                        drop(server);
+                        sleep(config.dial_interval).await;
                     }
                 }.instrument(span!(Level::DEBUG, "dialer"))
             });
+
+            if let Some(rotation) = outbound_rotation {
+                tasks.spawn_with_name("outbound rotation", {
+                    let server = Arc::downgrade(&server);
+                    async move {
+                        loop {
+                            sleep(rotation.interval).await;
+
+                            let server = match server.upgrade() {
+                                Some(server) if !server.is_shut_down() => server,
+                                _ => return,
+                            };
+                            if server.is_draining() {
+                                continue;
+                            }
+
+                            if let Some(peer) = server.rotation_victim(&rotation) {
+                                debug!("Rotating out outbound peer {}", peer.id());
+                                // Otherwise the freed slot could be dialed right back to the same peer.
+                                server.dial_history.defer(peer.id(), rotation.cooldown);
+                                let _ =
+                                    peer.disconnect(DisconnectReason::DisconnectRequested).await;
+                            }
+                        }
+                    }
+                    .instrument(span!(Level::DEBUG, "outbound rotation"))
+                });
+            }
         }
 
         Ok(server)
@@ -709,31 +2043,46 @@ impl<C: CapabilityServer> Swarm<C> {
         let tasks = self.tasks.clone();
         let streams = self.streams.clone();
         let node_filter = self.node_filter.clone();
+        let peer_limits = self.peer_limits.clone();
+        let reservations = self.reservations.clone();
+        let eviction_policy = self.eviction_policy.clone();
+        let static_peers = self.static_peers.clone();
 
         let capabilities = self.capabilities.clone();
         let capability_set = capabilities.get_capabilities().to_vec();
         let capability_server = self.capability_server.clone();
 
         let secret_key = self.secret_key;
+        let local_id = self.local_id();
         let protocol_version = self.protocol_version;
         let client_version = self.client_version.clone();
-        let port = self.port;
+        let port = self.endpoints.hello_port(addr);
+        let proxy = self.proxy.clone();
+        let events = self.events.clone();
+        let ban_list = self.ban_list.clone();
+        let reputation = self.reputation.clone();
+        let trusted_peers = self.trusted_peers.clone();
+        let network_policy = self.network_policy.clone();
+        let dial_history = self.dial_history.clone();
+        let known_peers = self.known_peers.clone();
+        let draining = self.draining.clone();
+        let config = self.config.clone();
 
         let (tx, rx) = tokio::sync::oneshot::channel();
         let connection_id = Uuid::new_v4();
         let currently_connecting = self.currently_connecting.clone();
+        currently_connecting.fetch_add(1, Ordering::Relaxed);
 
         // Start reaper task that will terminate this connection if connection future gets dropped.
         tasks.spawn_with_name(format!("connection {} reaper", connection_id), {
             let cid = connection_id;
             let streams = streams.clone();
-            let currently_connecting = currently_connecting.clone();
             async move {
                 if rx.await.is_err() {
                     let mut s = streams.lock();
                     if let Entry::Occupied(entry) = s.mapping.entry(remote_id) {
                         // If this is the same connection attempt, then remove.
-                        if let PeerState::Connecting { connection_id } = entry.get() {
+                        if let PeerState::Connecting { connection_id, .. } = entry.get() {
                             if *connection_id == cid {
                                 trace!("Reaping failed outbound connection: {}/{}", remote_id, cid);
 
@@ -750,13 +2099,69 @@ impl<C: CapabilityServer> Swarm<C> {
             trace!("Received request to add peer {}", remote_id);
             let mut inserted = false;
 
-            currently_connecting.fetch_add(1, Ordering::Relaxed);
+            if remote_id == local_id {
+                return Err(HandshakeError::ConnectedToSelf.into());
+            }
+
+            if draining.load(Ordering::Relaxed) {
+                debug!("Not connecting to peer {}: draining", remote_id);
+                return Ok(false);
+            }
+
+            if let Some(ban) = ban_list.find(Some(remote_id), Some(addr.ip())) {
+                debug!(
+                    "Not connecting to banned peer {} at {}: {}",
+                    remote_id, addr, ban.reason
+                );
+                return Ok(false);
+            }
+
+            if let Err(violation) = network_policy.read().check_addr(addr.ip()) {
+                debug!(
+                    "Not connecting to peer {} at {}: {}",
+                    remote_id, addr, violation
+                );
+                return Ok(false);
+            }
+
+            if streams.lock().mapping.contains_key(&remote_id) {
+                debug!("We are already connected or connecting to remote peer {}!", remote_id);
+                return Ok(false);
+            }
+
+            let filtered = check_peer && !trusted_peers.read().contains(&remote_id);
+            let request = NodeFilterRequest {
+                id: remote_id,
+                addr,
+                direction: ConnectionDirection::Outbound,
+                counts: streams.lock().counts(),
+                reputation: reputation.score(remote_id),
+            };
+            if filtered && !node_filter.allow(request).await {
+                trace!("Node filter rejected peer {}", remote_id);
+                let _ = events.send(SwarmEvent::NodeFilterRejected {
+                    id: remote_id,
+                    addr,
+                    direction: ConnectionDirection::Outbound,
+                });
+                return Ok(false);
+            }
 
             {
                 let mut streams = streams.lock();
-                let node_filter = node_filter.lock();
 
-                let connection_num = streams.mapping.len();
+                let wants_outbound = streams.wants_outbound(&SlotRules {
+                    limits: *peer_limits.read(),
+                    reservations: &reservations.read(),
+                    eviction_policy: &*eviction_policy,
+                    trusted_peers: &trusted_peers.read(),
+                    static_peers: &static_peers,
+                    reputation: &reputation,
+                });
+                // Subnet caps apply to every dial, as they do to inbound connections.
+                let subnet_res = network_policy
+                    .read()
+                    .check_subnets(addr.ip(), streams.ips());
 
                 match streams.mapping.entry(remote_id) {
                     Entry::Occupied(key) => {
@@ -771,12 +2176,25 @@ impl<C: CapabilityServer> Swarm<C> {
                         );
                     }
                     Entry::Vacant(vacant) => {
-                        if check_peer && !node_filter.allow(connection_num, remote_id) {
-                            trace!("rejecting peer {}", remote_id);
+                        if let Err(violation) = subnet_res {
+                            debug!(
+                                "Not connecting to peer {} at {}: {}",
+                                remote_id, addr, violation
+                            );
+                        } else if filtered && !wants_outbound {
+                            trace!("Too many peers, not connecting to {}", remote_id);
                         } else {
                             debug!("connecting to peer {} at {}", remote_id, addr);
 
-                            vacant.insert(PeerState::Connecting { connection_id });
+                            vacant.insert(PeerState::Connecting {
+                                connection_id,
+                                info: ConnectingPeerInfo {
+                                    id: remote_id,
+                                    addr,
+                                    direction: ConnectionDirection::Outbound,
+                                    started_at: Instant::now(),
+                                },
+                            });
                             inserted = true;
                         }
                     }
@@ -787,51 +2205,222 @@ impl<C: CapabilityServer> Swarm<C> {
                 return Ok(false);
             }
 
+            let _ = events.send(SwarmEvent::DialStarted {
+                id: remote_id,
+                addr,
+            });
+
             // Connecting to peer is a long running operation so we have to break the mutex lock.
+            let settings = config.read().clone();
+            let timed_out = |stage| {
+                debug!("Timed out connecting to peer {}: {}", remote_id, stage);
+                DialError::Timeout
+            };
+            let handshake_failed = |e| DialError::Handshake(Arc::new(e));
             let peer_res = async {
-                let transport = TcpStream::connect(addr).await?;
-                PeerStream::connect(
-                    transport,
-                    secret_key,
-                    remote_id,
-                    protocol_version,
-                    client_version,
-                    capability_set,
-                    port,
+                let transport = tokio::time::timeout(settings.tcp_connect_timeout, async {
+                    if let Some(proxy) = &proxy {
+                        proxy
+                            .connect(addr)
+                            .await
+                            .map_err(|e| DialError::Proxy(Arc::new(e)))
+                    } else {
+                        TcpStream::connect(addr)
+                            .await
+                            .map_err(|e| DialError::Connect(e.kind()))
+                    }
+                })
+                .await
+                .map_err(|_| timed_out("TCP connect"))??;
+                let transport = tokio::time::timeout(
+                    settings.ecies_timeout,
+                    ECIESStream::connect(transport, secret_key, remote_id),
+                )
+                .await
+                .map_err(|_| timed_out("ECIES handshake"))?
+                .map_err(|e| handshake_failed(HandshakeError::Ecies(e)))?;
+                let peer = tokio::time::timeout(
+                    settings.hello_timeout,
+                    PeerStream::new(
+                        transport,
+                        secret_key,
+                        protocol_version,
+                        client_version,
+                        capability_set,
+                        port,
+                    ),
                 )
                 .await
+                .map_err(|_| timed_out("Hello"))?
+                .map_err(|e| match HandshakeError::from_hello(e) {
+                    HandshakeError::Disconnected(reason) => DialError::Disconnected(reason),
+                    e => handshake_failed(e),
+                })?;
+                if peer.remote_id() != remote_id {
+                    let received = peer.remote_id();
+                    reject_peer(
+                        peer,
+                        DisconnectReason::UnexpectedHandshakeIdentity,
+                        settings.reject_grace_period,
+                    )
+                    .await;
+                    return Err(handshake_failed(HandshakeError::UnexpectedIdentity {
+                        expected: remote_id,
+                        received,
+                    }));
+                }
+                Ok(peer)
             }
-            .await;
+            .await
+            .map_err(|error| {
+                dial_history.record(remote_id, addr, DialOutcome::Failed(error.clone()));
+                match &error {
+                    DialError::Handshake(error) => {
+                        reputation.report(remote_id, ReputationEvent::HandshakeFailure);
+                        let _ = events.send(SwarmEvent::HandshakeFailed {
+                            id: Some(remote_id),
+                            addr,
+                            direction: ConnectionDirection::Outbound,
+                            error: error.clone(),
+                        });
+                    }
+                    DialError::Timeout => {
+                        reputation.report(remote_id, ReputationEvent::Timeout);
+                    }
+                    _ => {}
+                }
+                let _ = events.send(SwarmEvent::DialFailed {
+                    id: remote_id,
+                    addr,
+                    error: error.clone(),
+                });
+                anyhow::Error::from(error)
+            });
 
-            let s = streams.clone();
-            let mut s = s.lock();
-            let PeerStreams { mapping } = &mut *s;
-
-            // Adopt the new connection if the peer has not been dropped or superseded by incoming connection.
-            if let Entry::Occupied(mut peer_state) = mapping.entry(remote_id) {
-                if !peer_state.get().is_connected() {
-                    match peer_res {
-                        Ok(peer) => {
-                            assert_eq!(peer.remote_id(), remote_id);
-                            debug!("New peer connected: {}", remote_id);
-
-                            *peer_state.get_mut() = PeerState::Connected(setup_peer_state(
-                                Arc::downgrade(&streams),
-                                capability_server,
-                                remote_id,
-                                peer,
-                            ));
+            let (rejected, evicted) = {
+                let mut s = streams.lock();
+                // Negotiated capabilities are known now, check them against reservations.
+                let slot = match &peer_res {
+                    Ok(peer) if filtered => s.slot(
+                        &EvictionCandidate {
+                            id: remote_id,
+                            addr,
+                            direction: ConnectionDirection::Outbound,
+                            connected_at: Instant::now(),
+                            capabilities: peer.capabilities().to_vec(),
+                            reputation: reputation.score(remote_id),
+                            is_static: static_peers.contains(remote_id),
+                        },
+                        &SlotRules {
+                            limits: *peer_limits.read(),
+                            reservations: &reservations.read(),
+                            eviction_policy: &*eviction_policy,
+                            trusted_peers: &trusted_peers.read(),
+                            static_peers: &static_peers,
+                            reputation: &reputation,
+                        },
+                    ),
+                    _ => Slot::Free,
+                };
 
-                            let _ = tx.send(());
-                            return Ok(true);
-                        }
-                        Err(e) => {
-                            debug!("peer disconnected with error {}", e);
-                            peer_state.remove();
-                            return Err(e);
+                // Adopt the new connection if the peer has not been dropped or superseded by incoming connection.
+                match s.mapping.entry(remote_id) {
+                    Entry::Occupied(mut peer_state)
+                        if matches!(peer_state.get(), PeerState::Connecting { connection_id: cid, .. } if *cid == connection_id) =>
+                    {
+                        match peer_res {
+                            // Swarm started draining during the handshake.
+                            Ok(peer) if draining.load(Ordering::Relaxed) => {
+                                debug!("Not adopting peer {}: draining", remote_id);
+                                peer_state.remove();
+                                (Some((peer, DisconnectReason::ClientQuitting)), None)
+                            }
+                            Ok(peer) => {
+                                match slot {
+                                    Slot::Full(violation) => {
+                                        debug!(
+                                            "No slot for peer {}: {}",
+                                            remote_id,
+                                            violation.map_or_else(
+                                                || "too many peers".to_string(),
+                                                |v| v.to_string()
+                                            )
+                                        );
+                                        peer_state.remove();
+                                        (Some((peer, DisconnectReason::TooManyPeers)), None)
+                                    }
+                                    slot => {
+                                        debug!("New peer connected: {}", remote_id);
+                                        *peer_state.get_mut() =
+                                            PeerState::Connected(setup_peer_state(
+                                                Arc::downgrade(&streams),
+                                                capability_server,
+                                                events,
+                                                reputation,
+                                                config,
+                                                addr,
+                                                ConnectionDirection::Outbound,
+                                                Some(PendingDial {
+                                                    dial_history,
+                                                    known_peers,
+                                                    record: NodeRecord {
+                                                        id: remote_id,
+                                                        addr,
+                                                    },
+                                                }),
+                                                peer,
+                                            ));
+
+                                        let _ = tx.send(());
+                                        match slot {
+                                            Slot::Evict(victim, commands) => {
+                                                debug!(
+                                                    "Evicting peer {} to make room for {}",
+                                                    victim, remote_id
+                                                );
+                                                (None, Some(commands))
+                                            }
+                                            _ => return Ok(true),
+                                        }
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                debug!("peer disconnected with error {}", e);
+                                peer_state.remove();
+                                return Err(e);
+                            }
                         }
                     }
+                    Entry::Occupied(_) => {
+                        debug!("Connection to {} superseded by incoming connection", remote_id);
+                        (
+                            peer_res
+                                .ok()
+                                .map(|peer| (peer, DisconnectReason::AlreadyConnected)),
+                            None,
+                        )
+                    }
+                    // Dropped by shutdown, tell the peer why.
+                    Entry::Vacant(_) if draining.load(Ordering::Relaxed) => (
+                        peer_res
+                            .ok()
+                            .map(|peer| (peer, DisconnectReason::ClientQuitting)),
+                        None,
+                    ),
+                    Entry::Vacant(_) => (None, None),
                 }
+            };
+
+            if let Some(commands) = evicted {
+                let _ = commands
+                    .send(PeerCommand::Disconnect(DisconnectReason::TooManyPeers))
+                    .await;
+                return Ok(true);
+            }
+
+            if let Some((peer, reason)) = rejected {
+                reject_peer(peer, reason, settings.reject_grace_period).await;
             }
 
             Ok(false)
@@ -839,6 +2428,473 @@ impl<C: CapabilityServer> Swarm<C> {
         .instrument(span!(Level::DEBUG, "add peer",))
     }
 
+    /// Our node ID.
+    pub fn local_id(&self) -> PeerId {
+        pk2id(&PublicKey::from_secret_key(SECP256K1, &self.secret_key))
+    }
+
+    /// Addresses we are accepting connections on, one per listener.
+    pub fn listen_addrs(&self) -> &[SocketAddr] {
+        &self.endpoints.listen_addrs
+    }
+
+    /// Externally visible addresses advertised to peers, at most one per address family.
+    pub fn external_addrs(&self) -> Vec<SocketAddr> {
+        self.endpoints.external_addrs.read().to_vec()
+    }
+
+    /// Update the advertised external address of `addr`'s family, e.g. when NAT mapping changes.
+    /// Only affects connections established afterwards.
+    pub fn set_external_addr(&self, addr: SocketAddr) {
+        self.endpoints.external_addrs.write().set(addr);
+    }
+
+    /// Stop advertising external addresses and fall back to listen addresses.
+    pub fn clear_external_addrs(&self) {
+        *self.endpoints.external_addrs.write() = ExternalAddrs::default();
+    }
+
+    /// Node records under which we can be reached, one per address family.
+    /// Listeners bound to an unspecified address are left out, unless an external address takes their place.
+    pub fn local_node_records(&self) -> Vec<NodeRecord> {
+        let id = self.local_id();
+        self.endpoints
+            .advertised_addrs()
+            .into_iter()
+            .filter(|addr| !addr.ip().is_unspecified())
+            .map(|addr| NodeRecord { id, addr })
+            .collect()
+    }
+
+    /// Peers we have established connection with.
+    pub fn connected_peers(&self) -> Vec<PeerInfo> {
+        self.streams
+            .lock()
+            .mapping
+            .values()
+            .filter_map(|state| match state {
+                PeerState::Connected(ConnectedPeerState { info, .. }) => Some(info.clone()),
+                PeerState::Connecting { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Peers we are still establishing connection with.
+    pub fn connecting_peers(&self) -> Vec<ConnectingPeerInfo> {
+        self.streams
+            .lock()
+            .mapping
+            .values()
+            .filter_map(|state| match state {
+                PeerState::Connecting { info, .. } => Some(info.clone()),
+                PeerState::Connected(_) => None,
+            })
+            .collect()
+    }
+
+    /// Information about connected peer, if any.
+    pub fn peer_info(&self, id: PeerId) -> Option<PeerInfo> {
+        match self.streams.lock().mapping.get(&id) {
+            Some(PeerState::Connected(ConnectedPeerState { info, .. })) => Some(info.clone()),
+            _ => None,
+        }
+    }
+
+    /// Outbound peer to replace with a fresh one, if the pool is full.
+    fn rotation_victim(&self, rotation: &OutboundRotation) -> Option<PeerHandle> {
+        let streams = self.streams.lock();
+        let reservations = self.reservations.read();
+        let trusted_peers = self.trusted_peers.read();
+        if streams.wants_outbound(&SlotRules {
+            limits: *self.peer_limits.read(),
+            reservations: &reservations,
+            eviction_policy: &*self.eviction_policy,
+            trusted_peers: &trusted_peers,
+            static_peers: &self.static_peers,
+            reputation: &self.reputation,
+        }) {
+            return None;
+        }
+
+        let tally = streams.tally(None);
+        let candidates = streams
+            .mapping
+            .values()
+            .filter_map(|state| match state {
+                PeerState::Connected(state)
+                    if !trusted_peers.contains(&state.info.id)
+                        && tally.is_surplus(&reservations, &state.info.capabilities) =>
+                {
+                    Some(state.eviction_candidate(&self.reputation, &self.static_peers))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        match streams
+            .mapping
+            .get(&rotation.select(Instant::now(), &candidates)?)
+        {
+            Some(PeerState::Connected(state)) => Some(state.handle()),
+            _ => None,
+        }
+    }
+
+    /// Handle to a connected peer, if any.
+    pub fn peer(&self, id: PeerId) -> Option<PeerHandle> {
+        match self.streams.lock().mapping.get(&id) {
+            Some(PeerState::Connected(state)) => Some(state.handle()),
+            _ => None,
+        }
+    }
+
+    /// Number of peers per connection direction, including outbound connections being established.
+    pub fn peer_counts(&self) -> PeerCounts {
+        self.streams.lock().counts()
+    }
+
+    pub fn capability_reservations(&self) -> Vec<CapabilityReservation> {
+        self.reservations.read().clone()
+    }
+
+    /// Replace capability reservations. Like peer limits, they only affect peers admitted afterwards.
+    pub fn set_capability_reservations(&self, reservations: Vec<CapabilityReservation>) {
+        *self.reservations.write() = reservations;
+    }
+
+    pub fn peer_limits(&self) -> PeerLimits {
+        *self.peer_limits.read()
+    }
+
+    /// Change peer limits. Peers above the new limits are not disconnected, but no new ones are admitted
+    /// until the counts fall below.
+    pub fn set_peer_limits(&self, limits: PeerLimits) {
+        *self.peer_limits.write() = limits;
+    }
+
+    /// Number of connected peers that negotiated each capability.
+    pub fn capability_peer_counts(&self) -> HashMap<CapabilityId, usize> {
+        let mut counts = HashMap::new();
+        for state in self.streams.lock().mapping.values() {
+            if let PeerState::Connected(ConnectedPeerState { info, .. }) = state {
+                for &cap in &info.capabilities {
+                    *counts.entry(CapabilityId::from(cap)).or_default() += 1;
+                }
+            }
+        }
+        counts
+    }
+
+    /// Ban a peer, IP address or subnet for `duration`, or permanently if `None`.
+    /// Live sessions matching the ban are disconnected.
+    pub fn ban(
+        &self,
+        target: BanTarget,
+        duration: Option<Duration>,
+        reason: String,
+    ) -> anyhow::Result<()> {
+        let res = self.ban_list.ban(target, duration, reason);
+
+        for state in self.streams.lock().mapping.values() {
+            if let PeerState::Connected(state) = state {
+                let matches = match target {
+                    BanTarget::Peer(id) => state.info.id == id,
+                    BanTarget::Ip(ip) => state.info.addr.ip() == ip,
+                    BanTarget::Subnet(cidr) => cidr.contains(&state.info.addr.ip()),
+                };
+                if matches {
+                    let handle = state.handle();
+                    self.tasks.spawn_with_name(
+                        format!("disconnect banned peer {}", handle.id()),
+                        async move {
+                            let _ = handle
+                                .disconnect(DisconnectReason::DisconnectRequested)
+                                .await;
+                        },
+                    );
+                }
+            }
+        }
+
+        res
+    }
+
+    /// Lift a ban. Returns `true` if the target was banned.
+    pub fn unban(&self, target: BanTarget) -> anyhow::Result<bool> {
+        self.ban_list.unban(target)
+    }
+
+    /// All active bans.
+    pub fn bans(&self) -> Vec<Ban> {
+        self.ban_list.bans()
+    }
+
+    /// Keep connection to this peer, redialing with exponential backoff after disconnects.
+    /// Returns `true` if the peer was not static before.
+    pub fn add_static_peer(&self, record: NodeRecord) -> bool {
+        self.static_peers.insert(record)
+    }
+
+    /// Stop redialing this peer. Existing connection is kept.
+    pub fn remove_static_peer(&self, id: PeerId) -> bool {
+        self.static_peers.remove(id)
+    }
+
+    pub fn static_peers(&self) -> Vec<NodeRecord> {
+        self.static_peers.records()
+    }
+
+    /// Accept this peer regardless of peer limits and node filter, both inbound and outbound.
+    /// Returns `true` if the peer was not trusted before.
+    pub fn add_trusted_peer(&self, id: PeerId) -> bool {
+        self.trusted_peers.write().insert(id)
+    }
+
+    pub fn remove_trusted_peer(&self, id: PeerId) -> bool {
+        self.trusted_peers.write().remove(&id)
+    }
+
+    pub fn trusted_peers(&self) -> Vec<PeerId> {
+        self.trusted_peers.read().iter().copied().collect()
+    }
+
+    pub fn network_policy(&self) -> NetworkPolicy {
+        self.network_policy.read().clone()
+    }
+
+    /// Replace network policy. Only affects connections established afterwards.
+    pub fn set_network_policy(&self, policy: NetworkPolicy) {
+        *self.network_policy.write() = policy;
+    }
+
+    /// Report peer behaviour to the reputation subsystem.
+    /// Peers whose score reaches ban threshold are disconnected and temporarily banned.
+    pub fn report_peer(&self, id: PeerId, event: ReputationEvent) {
+        if self.reputation.report(id, event) {
+            if let Some(handle) = self.peer(id) {
+                self.tasks.spawn_with_name(
+                    format!("disconnect misbehaving peer {}", id),
+                    async move {
+                        let _ = handle.disconnect(DisconnectReason::UselessPeer).await;
+                    },
+                );
+            }
+        }
+    }
+
+    /// Current reputation score of a peer, zero if nothing was reported about it.
+    pub fn reputation(&self, id: PeerId) -> i32 {
+        self.reputation.score(id)
+    }
+
+    /// Reputation scores of all peers with reported behaviour.
+    pub fn reputations(&self) -> HashMap<PeerId, i32> {
+        self.reputation.scores()
+    }
+
+    /// Subscribe to lifecycle events of this swarm.
+    /// Events emitted before subscription are not delivered, nor are those missed by a lagging subscriber.
+    pub fn subscribe(&self) -> impl Stream<Item = SwarmEvent> + Send + 'static {
+        BroadcastStream::new(self.events.subscribe()).filter_map(|event| match event {
+            Ok(event) => Some(event),
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                warn!("Swarm event subscriber lagged, {} events skipped", skipped);
+                None
+            }
+        })
+    }
+
+    /// Nodes we have connected to or imported, most successful first.
+    pub fn known_peers(&self) -> Vec<StoredPeer> {
+        self.known_peers.peers()
+    }
+
+    /// Import nodes from an enode list, one per line, and schedule them for dialing.
+    /// Returns the number of nodes not known before.
+    pub fn import_enodes(&self, enodes: &str) -> anyhow::Result<usize> {
+        let records = parse_enodes(enodes)?;
+        let imported = self.known_peers.import(records.iter().copied());
+        for record in records {
+            self.queue_dial(PEER_STORE_SOURCE, record);
+        }
+        Ok(imported)
+    }
+
+    /// Export known nodes as an enode list, one per line.
+    pub fn export_enodes(&self) -> String {
+        self.known_peers
+            .peers()
+            .into_iter()
+            .map(|peer| format!("{}\n", peer.record))
+            .collect()
+    }
+
+    /// Write known nodes to the peer store now rather than on the next periodic flush.
+    pub fn flush_peer_store(&self) -> anyhow::Result<()> {
+        self.known_peers.flush()
+    }
+
+    /// Recent outbound connection attempts, oldest first.
+    pub fn dial_history(&self) -> Vec<DialAttempt> {
+        self.dial_history.attempts()
+    }
+
+    /// Time left until the dialer may try this node again, if it is in backoff after failed attempts.
+    pub fn dial_backoff(&self, id: PeerId) -> Option<Duration> {
+        self.dial_history.backoff(id)
+    }
+
+    pub fn config(&self) -> SwarmConfig {
+        self.config.read().clone()
+    }
+
+    /// Replace timeouts and intervals. Invalid configuration is rejected and the current one is kept.
+    pub fn set_config(&self, config: SwarmConfig) -> anyhow::Result<()> {
+        config.validate()?;
+        *self.config.write() = config;
+        Ok(())
+    }
+
+    /// Stop accepting and dialing new connections while keeping existing ones, or resume if `false`.
+    /// A shut down swarm does not resume.
+    pub fn set_draining(&self, draining: bool) {
+        // Keep the shutdown flag borrowed, so that `shutdown` cannot interleave.
+        let shut_down = self.shutdown.borrow();
+        self.draining
+            .store(draining || *shut_down, Ordering::Relaxed);
+    }
+
+    /// Start a discovery source feeding the dialer. `factory` creates the stream, and creates it again each
+    /// time `restart_policy` restarts the source.
+    pub fn add_discovery(
+        &self,
+        name: impl Into<String>,
+        factory: impl Fn() -> Discovery + Send + Sync + 'static,
+        restart_policy: RestartPolicy,
+    ) -> anyhow::Result<()> {
+        let name = name.into();
+        let records = match &self.discovered {
+            Some(records) => records.clone(),
+            None => bail!("dialing is disabled"),
+        };
+        if name == PEER_STORE_SOURCE {
+            bail!("discovery source name {} is reserved", name);
+        }
+        let (generation, stopped) = self
+            .discovery
+            .insert(&name, restart_policy)
+            .ok_or_else(|| anyhow!("discovery source {} already exists", name))?;
+
+        let span = span!(Level::DEBUG, "discovery", name = &*name);
+        self.tasks.spawn_with_name(
+            format!("discovery {}", name),
+            DiscoveryTask {
+                name,
+                generation,
+                restart_policy,
+                factory: Box::new(factory),
+                sources: self.discovery.clone(),
+                config: self.config.clone(),
+                records,
+            }
+            .run(stopped)
+            .instrument(span),
+        );
+        Ok(())
+    }
+
+    /// Stop a discovery source and forget its statistics. Nodes it found stay queued for dialing.
+    pub fn remove_discovery(&self, name: &str) -> bool {
+        self.discovery.remove(name)
+    }
+
+    /// Discovery sources with their statistics, by name.
+    pub fn discovery_sources(&self) -> Vec<DiscoverySourceInfo> {
+        self.discovery.list()
+    }
+
+    /// Queue a node for the dialer, most reputable and most successful nodes first.
+    fn queue_dial(&self, source: &str, record: NodeRecord) {
+        if !self.reputation.allows_dial(record.id)
+            || self.streams.lock().mapping.contains_key(&record.id)
+        {
+            return;
+        }
+
+        let priority = self
+            .reputation
+            .score(record.id)
+            .saturating_add(self.known_peers.successes(record.id));
+        let config = self.config.read();
+        self.dial_scheduler.lock().push(
+            source,
+            config.dial_source_weight(source),
+            config.max_queued_dials,
+            record,
+            priority,
+        );
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::Relaxed)
+    }
+
+    pub fn is_shut_down(&self) -> bool {
+        *self.shutdown.borrow()
+    }
+
+    /// Gracefully shut down: stop listeners, the dialer and discovery, send `ClientQuitting` to every peer
+    /// and wait until their sessions are closed and reported to the capability server.
+    /// Peers still connected after `timeout` are dropped forcefully.
+    pub async fn shutdown(&self, timeout: Duration) -> anyhow::Result<()> {
+        let deadline = Instant::now() + timeout;
+        self.shutdown.send_replace(true);
+        self.draining.store(true, Ordering::Relaxed);
+        self.discovery.clear();
+
+        let handles = self
+            .streams
+            .lock()
+            .mapping
+            .values()
+            .filter_map(|state| match state {
+                PeerState::Connected(state) => Some(state.handle()),
+                PeerState::Connecting { .. } => None,
+            })
+            .collect::<Vec<_>>();
+        debug!("Shutting down, disconnecting {} peers", handles.len());
+        let _ = tokio::time::timeout(
+            deadline.saturating_duration_since(Instant::now()),
+            futures::future::join_all(
+                handles
+                    .iter()
+                    .map(|handle| handle.disconnect(DisconnectReason::ClientQuitting)),
+            ),
+        )
+        .await;
+
+        loop {
+            let connected = self
+                .streams
+                .lock()
+                .mapping
+                .values()
+                .filter(|state| state.is_connected())
+                .count();
+            if connected == 0 {
+                break;
+            }
+            if Instant::now() >= deadline {
+                warn!("Shutdown timed out, dropping {} remaining peers", connected);
+                break;
+            }
+            sleep(SHUTDOWN_POLL_INTERVAL).await;
+        }
+        self.streams.lock().mapping.clear();
+
+        self.known_peers.flush()
+    }
+
     /// Returns the number of peers we're currently dialing
     pub fn dialing(&self) -> usize {
         self.currently_connecting.load(Ordering::Relaxed)
@@ -852,3 +2908,406 @@ impl<C: CapabilityServer> Deref for Swarm<C> {
         &*self.capability_server
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrayvec::ArrayString;
+    use maplit::btreemap;
+
+    #[test]
+    fn advertised_addrs() {
+        let endpoints = Endpoints {
+            listen_addrs: vec![
+                "0.0.0.0:30303".parse().unwrap(),
+                "[::]:30303".parse().unwrap(),
+            ],
+            external_addrs: Default::default(),
+        };
+        endpoints
+            .external_addrs
+            .write()
+            .set("1.2.3.4:30304".parse().unwrap());
+        assert_eq!(
+            endpoints.advertised_addrs(),
+            vec![
+                "1.2.3.4:30304".parse::<SocketAddr>().unwrap(),
+                "[::]:30303".parse().unwrap()
+            ]
+        );
+
+        endpoints
+            .external_addrs
+            .write()
+            .set("[2001:db8::1]:30305".parse().unwrap());
+        assert_eq!(
+            endpoints.hello_port("[2001:db8::2]:1".parse().unwrap()),
+            30305
+        );
+        assert_eq!(endpoints.hello_port("5.6.7.8:1".parse().unwrap()), 30304);
+    }
+
+    #[test]
+    fn protocol_breach() {
+        assert!(is_protocol_breach(
+            &ProtocolBreach("unhandled reserved message".to_string()).into()
+        ));
+        assert!(!is_protocol_breach(&io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "connection reset"
+        )));
+    }
+
+    async fn swarm() -> Arc<Swarm<()>> {
+        swarm_with_key(SecretKey::new(&mut secp256k1::rand::thread_rng())).await
+    }
+
+    async fn swarm_with_key(secret_key: SecretKey) -> Arc<Swarm<()>> {
+        Swarm::builder()
+            .with_listen_options(ListenOptions {
+                listen_addrs: vec![ListenAddr::from(
+                    "127.0.0.1:0".parse::<SocketAddr>().unwrap(),
+                )],
+                max_inbound: 10,
+            })
+            .build(
+                btreemap! { CapabilityId { name: CapabilityName(ArrayString::from("eth").unwrap()), version: 66 } => 17 },
+                Arc::new(()),
+                secret_key,
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn introspection() {
+        let a = swarm().await;
+        let b = swarm().await;
+        assert!(a.add_peer(b.local_node_records()[0]).await.unwrap());
+
+        let peers = a.connected_peers();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].id, b.local_id());
+        assert_eq!(peers[0].direction, ConnectionDirection::Outbound);
+        assert_eq!(peers[0].capabilities.len(), 1);
+        assert!(a.connecting_peers().is_empty());
+        assert_eq!(
+            a.peer_counts(),
+            PeerCounts {
+                inbound: 0,
+                outbound: 1
+            }
+        );
+        assert_eq!(
+            a.peer_info(b.local_id()).unwrap().client_version,
+            peers[0].client_version
+        );
+        assert!(a.peer_info(a.local_id()).is_none());
+    }
+
+    async fn next_event(
+        events: &mut (impl Stream<Item = SwarmEvent> + Unpin),
+    ) -> Option<SwarmEvent> {
+        tokio::time::timeout(Duration::from_secs(5), events.next())
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn peer_handle_send() {
+        let a = swarm().await;
+        let b = swarm().await;
+        assert!(a.add_peer(b.local_node_records()[0]).await.unwrap());
+        let peer = a.peer(b.local_id()).unwrap();
+
+        let message = |id| Message {
+            id,
+            data: bytes::Bytes::from_static(b"\xc0"),
+        };
+        let eth = CapabilityName(ArrayString::from("eth").unwrap());
+        let les = CapabilityName(ArrayString::from("les").unwrap());
+        assert!(peer.send(les, message(0)).await.is_err());
+        assert!(peer.send(eth, message(17)).await.is_err());
+        peer.send(eth, message(0)).await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while peer.stats().messages_out == 0 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(peer.stats().messages_out, 1);
+        assert_eq!(peer.stats().bytes_out, 1);
+    }
+
+    #[tokio::test]
+    async fn subnet_caps() {
+        let a = swarm().await;
+        a.set_network_policy(NetworkPolicy {
+            max_per_subnet24: Some(1),
+            ..Default::default()
+        });
+        let b = swarm().await;
+        let c = swarm().await;
+
+        // Explicitly added peers are subject to subnet caps too.
+        assert!(a.add_peer(b.local_node_records()[0]).await.unwrap());
+        assert!(!a.add_peer(c.local_node_records()[0]).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn rejected_dial() {
+        let a = swarm().await;
+        let b = swarm().await;
+        b.set_peer_limits(PeerLimits::new(0));
+        let mut events = Box::pin(a.subscribe());
+
+        // Remote without free slots completes Hello before disconnecting, which is not a success.
+        let record = b.local_node_records()[0];
+        assert!(a.add_peer(record).await.unwrap());
+        loop {
+            match next_event(&mut events).await {
+                Some(SwarmEvent::PeerDisconnected {
+                    initiator, reason, ..
+                }) => {
+                    assert!(matches!(initiator, DisconnectInitiator::Remote));
+                    assert_eq!(reason, DisconnectReason::TooManyPeers);
+                    break;
+                }
+                Some(_) => {}
+                None => panic!("peer was not disconnected"),
+            }
+        }
+        assert!(matches!(
+            a.dial_history()[..],
+            [DialAttempt {
+                outcome: DialOutcome::Failed(DialError::Disconnected(
+                    DisconnectReason::TooManyPeers
+                )),
+                ..
+            }]
+        ));
+        assert!(a.dial_backoff(record.id).is_some());
+        assert!(a.known_peers().is_empty());
+    }
+
+    #[tokio::test]
+    async fn events() {
+        let a = swarm().await;
+        let b = swarm().await;
+        let mut events = Box::pin(a.subscribe());
+
+        // Handshake with an unexpected key fails and carries a typed error.
+        let mut record = b.local_node_records()[0];
+        record.id = PeerId::random();
+        assert!(a.add_peer(record).await.is_err());
+        assert!(matches!(
+            next_event(&mut events).await,
+            Some(SwarmEvent::DialStarted { id, .. }) if id == record.id
+        ));
+        assert!(matches!(
+            next_event(&mut events).await,
+            Some(SwarmEvent::HandshakeFailed { id: Some(id), direction: ConnectionDirection::Outbound, error, .. })
+                if id == record.id && matches!(*error, HandshakeError::Ecies(_))
+        ));
+        assert!(matches!(
+            next_event(&mut events).await,
+            Some(SwarmEvent::DialFailed {
+                error: DialError::Handshake(_),
+                ..
+            })
+        ));
+
+        let record = b.local_node_records()[0];
+        assert!(a.add_peer(record).await.unwrap());
+        assert!(matches!(
+            next_event(&mut events).await,
+            Some(SwarmEvent::DialStarted { id, .. }) if id == record.id
+        ));
+        assert!(matches!(
+            next_event(&mut events).await,
+            Some(SwarmEvent::PeerConnected(PeerInfo { id, .. })) if id == record.id
+        ));
+
+        a.peer(record.id)
+            .unwrap()
+            .disconnect(DisconnectReason::DisconnectRequested)
+            .await
+            .unwrap();
+        assert!(matches!(
+            next_event(&mut events).await,
+            Some(SwarmEvent::PeerDisconnected {
+                id,
+                initiator: DisconnectInitiator::Local,
+                reason: DisconnectReason::DisconnectRequested,
+            }) if id == record.id
+        ));
+    }
+
+    #[tokio::test]
+    async fn shutdown() {
+        let a = swarm().await;
+        let b = swarm().await;
+        let mut events = Box::pin(b.subscribe());
+        assert!(a.add_peer(b.local_node_records()[0]).await.unwrap());
+
+        a.shutdown(Duration::from_secs(5)).await.unwrap();
+        assert!(a.connected_peers().is_empty());
+        loop {
+            match next_event(&mut events).await.unwrap() {
+                SwarmEvent::PeerDisconnected {
+                    initiator, reason, ..
+                } => {
+                    assert_eq!(initiator, DisconnectInitiator::Remote);
+                    assert_eq!(reason, DisconnectReason::ClientQuitting);
+                    break;
+                }
+                _ => continue,
+            }
+        }
+
+        // A shut down swarm stays closed.
+        a.set_draining(false);
+        assert!(a.is_draining());
+        assert!(!a.add_peer(b.local_node_records()[0]).await.unwrap());
+        let addr = a.local_node_records()[0].addr;
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while TcpStream::connect(addr).await.is_ok() {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn simultaneous_connections() {
+        for _ in 0..2 {
+            let a = swarm().await;
+            let b = swarm().await;
+            let mut a_events = Box::pin(a.subscribe());
+            let mut b_events = Box::pin(b.subscribe());
+
+            let _ = tokio::join!(
+                a.add_peer(b.local_node_records()[0]),
+                b.add_peer(a.local_node_records()[0])
+            );
+
+            // Connection dialed by the node with greater ID is kept on both sides.
+            let (a_kept, b_kept) = if a.local_id() > b.local_id() {
+                (ConnectionDirection::Outbound, ConnectionDirection::Inbound)
+            } else {
+                (ConnectionDirection::Inbound, ConnectionDirection::Outbound)
+            };
+            for (events, kept) in [(&mut a_events, a_kept), (&mut b_events, b_kept)] {
+                loop {
+                    match next_event(events).await.unwrap() {
+                        SwarmEvent::PeerConnected(info) if info.direction == kept => break,
+                        _ => continue,
+                    }
+                }
+            }
+
+            let a_peers = a.connected_peers();
+            let b_peers = b.connected_peers();
+            assert_eq!(a_peers.len(), 1);
+            assert_eq!(b_peers.len(), 1);
+            assert!(a.connecting_peers().is_empty());
+            assert!(b.connecting_peers().is_empty());
+            assert_eq!(a_peers[0].direction, a_kept);
+            assert_eq!(b_peers[0].direction, b_kept);
+        }
+    }
+
+    #[tokio::test]
+    async fn established_outbound_replaced() {
+        let mut keys = [
+            SecretKey::new(&mut secp256k1::rand::thread_rng()),
+            SecretKey::new(&mut secp256k1::rand::thread_rng()),
+        ];
+        keys.sort_by_key(|key| pk2id(&PublicKey::from_secret_key(SECP256K1, key)));
+        let a = swarm_with_key(keys[0]).await;
+        let mut events = Box::pin(a.subscribe());
+
+        // Outbound session to the node with greater ID is fully established first.
+        let remote = swarm_with_key(keys[1]).await;
+        assert!(a.add_peer(remote.local_node_records()[0]).await.unwrap());
+
+        // Its inbound connection still wins, regardless of the outbound session's age.
+        let record = a.local_node_records()[0];
+        let _inbound = PeerStream::connect(
+            TcpStream::connect(record.addr).await.unwrap(),
+            keys[1],
+            record.id,
+            ProtocolVersion::V5,
+            "test".to_string(),
+            vec![CapabilityInfo::new(
+                CapabilityId {
+                    name: CapabilityName(ArrayString::from("eth").unwrap()),
+                    version: 66,
+                },
+                17,
+            )],
+            0,
+        )
+        .await
+        .unwrap();
+        loop {
+            match next_event(&mut events).await.unwrap() {
+                SwarmEvent::PeerConnected(info)
+                    if info.direction == ConnectionDirection::Inbound =>
+                {
+                    break
+                }
+                _ => continue,
+            }
+        }
+
+        let peers = a.connected_peers();
+        assert_eq!(peers.len(), 1);
+        assert_eq!(peers[0].id, remote.local_id());
+        assert_eq!(peers[0].direction, ConnectionDirection::Inbound);
+    }
+
+    #[tokio::test]
+    async fn outbound_slots_with_eviction() {
+        let a = swarm().await;
+        a.set_peer_limits(PeerLimits {
+            max_peers: 2,
+            max_inbound: 2,
+            max_outbound: 2,
+        });
+        let b = swarm().await;
+        let c = swarm().await;
+        let mut events = Box::pin(a.subscribe());
+        assert!(b.add_peer(a.local_node_records()[0]).await.unwrap());
+        assert!(c.add_peer(a.local_node_records()[0]).await.unwrap());
+        for _ in 0..2 {
+            while !matches!(
+                next_event(&mut events).await.unwrap(),
+                SwarmEvent::PeerConnected(_)
+            ) {}
+        }
+
+        // Inbound peers fill the pool, so only eviction leaves room for an outbound one.
+        let slots = |eviction_policy: &dyn EvictionPolicy| {
+            a.streams.lock().outbound_slots(&SlotRules {
+                limits: *a.peer_limits.read(),
+                reservations: &a.reservations.read(),
+                eviction_policy,
+                trusted_peers: &a.trusted_peers.read(),
+                static_peers: &a.static_peers,
+                reputation: &a.reputation,
+            })
+        };
+        assert_eq!(slots(&()), 0);
+        assert_eq!(
+            slots(&DefaultEvictionPolicy {
+                protect_by_reputation: 0,
+                protect_by_subnet: 0,
+            }),
+            1
+        );
+    }
+}
@@ -0,0 +1,98 @@
+use crate::{eviction::EvictionCandidate, rlpx::ConnectionDirection, types::PeerId};
+use anyhow::bail;
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+/// Periodic replacement of outbound peers once the pool is full, so that the outbound set keeps sampling
+/// the network instead of freezing.
+///
+/// Each `interval` the lowest-scored outbound peer connected for at least `min_age`, oldest among equals,
+/// is disconnected and the freed slot is dialed from discovery. Static, trusted and protected peers, as
+/// well as peers needed for capability reservations, are never rotated.
+#[derive(Clone, Debug)]
+pub struct OutboundRotation {
+    pub interval: Duration,
+    pub min_age: Duration,
+    /// Time before a rotated-out peer may be dialed from discovery again.
+    pub cooldown: Duration,
+    pub protected_peers: HashSet<PeerId>,
+}
+
+impl Default for OutboundRotation {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(10 * 60),
+            min_age: Duration::from_secs(30 * 60),
+            cooldown: Duration::from_secs(60 * 60),
+            protected_peers: HashSet::new(),
+        }
+    }
+}
+
+impl OutboundRotation {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.interval == Duration::from_secs(0) {
+            bail!("rotation interval must not be zero");
+        }
+        Ok(())
+    }
+
+    pub(crate) fn select(&self, now: Instant, peers: &[EvictionCandidate]) -> Option<PeerId> {
+        peers
+            .iter()
+            .filter(|peer| {
+                peer.direction == ConnectionDirection::Outbound
+                    && !peer.is_static
+                    && !self.protected_peers.contains(&peer.id)
+                    && now.saturating_duration_since(peer.connected_at) >= self.min_age
+            })
+            .min_by_key(|peer| (peer.reputation, Reverse(now - peer.connected_at)))
+            .map(|peer| peer.id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select() {
+        let now = Instant::now();
+        let peer = |direction, age, reputation| EvictionCandidate {
+            id: PeerId::random(),
+            addr: "1.2.3.4:30303".parse().unwrap(),
+            direction,
+            connected_at: now - Duration::from_secs(age),
+            capabilities: vec![],
+            reputation,
+            is_static: false,
+        };
+        let mut rotation = OutboundRotation {
+            interval: Duration::from_secs(60),
+            min_age: Duration::from_secs(100),
+            cooldown: Duration::from_secs(60 * 60),
+            protected_peers: HashSet::new(),
+        };
+
+        let peers = vec![
+            peer(ConnectionDirection::Inbound, 1000, -10),
+            peer(ConnectionDirection::Outbound, 50, -10),
+            peer(ConnectionDirection::Outbound, 200, 5),
+            peer(ConnectionDirection::Outbound, 300, 5),
+            peer(ConnectionDirection::Outbound, 150, 10),
+        ];
+        assert_eq!(rotation.select(now, &peers), Some(peers[3].id));
+
+        rotation.protected_peers.insert(peers[3].id);
+        assert_eq!(rotation.select(now, &peers), Some(peers[2].id));
+
+        assert_eq!(rotation.select(now, &peers[..2]), None);
+
+        assert!(rotation.validate().is_ok());
+        rotation.interval = Duration::from_secs(0);
+        assert!(rotation.validate().is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use crate::types::{NodeRecord, PeerId};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+/// Sessions lasting at least this long reset the backoff.
+const STABLE_SESSION: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct StaticPeer {
+    addr: SocketAddr,
+    backoff: Duration,
+    next_attempt: Instant,
+    connected_since: Option<Instant>,
+    dialing: bool,
+}
+
+/// Peers that are kept connected, redialed with exponential backoff.
+#[derive(Debug, Default)]
+pub(crate) struct StaticPeers {
+    peers: Mutex<HashMap<PeerId, StaticPeer>>,
+}
+
+impl StaticPeers {
+    /// Add peer or update its address. Returns `true` if the peer is new.
+    pub(crate) fn insert(&self, record: NodeRecord) -> bool {
+        let mut peers = self.peers.lock();
+        if let Some(peer) = peers.get_mut(&record.id) {
+            peer.addr = record.addr;
+            return false;
+        }
+        peers.insert(
+            record.id,
+            StaticPeer {
+                addr: record.addr,
+                backoff: MIN_BACKOFF,
+                next_attempt: Instant::now(),
+                connected_since: None,
+                dialing: false,
+            },
+        );
+        true
+    }
+
+    pub(crate) fn remove(&self, id: PeerId) -> bool {
+        self.peers.lock().remove(&id).is_some()
+    }
+
+    pub(crate) fn contains(&self, id: PeerId) -> bool {
+        self.peers.lock().contains_key(&id)
+    }
+
+    pub(crate) fn records(&self) -> Vec<NodeRecord> {
+        self.peers
+            .lock()
+            .iter()
+            .map(|(&id, peer)| NodeRecord {
+                id,
+                addr: peer.addr,
+            })
+            .collect()
+    }
+
+    /// Peers that are not connected and whose backoff has elapsed. They are marked as being dialed,
+    /// `dial_finished` must be called for each of them.
+    pub(crate) fn due(&self, now: Instant, is_present: impl Fn(PeerId) -> bool) -> Vec<NodeRecord> {
+        let mut due = Vec::new();
+        for (&id, peer) in self.peers.lock().iter_mut() {
+            if peer.dialing {
+                continue;
+            }
+
+            if is_present(id) {
+                peer.connected_since.get_or_insert(now);
+                continue;
+            }
+
+            if let Some(connected_since) = peer.connected_since.take() {
+                // Peer has disconnected since we last looked.
+                if now.saturating_duration_since(connected_since) >= STABLE_SESSION {
+                    peer.backoff = MIN_BACKOFF;
+                    peer.next_attempt = now;
+                } else {
+                    peer.schedule_retry(now);
+                }
+            }
+
+            if peer.next_attempt <= now {
+                peer.dialing = true;
+                due.push(NodeRecord {
+                    id,
+                    addr: peer.addr,
+                });
+            }
+        }
+        due
+    }
+
+    pub(crate) fn dial_finished(&self, id: PeerId, connected: bool, now: Instant) {
+        if let Some(peer) = self.peers.lock().get_mut(&id) {
+            peer.dialing = false;
+            if connected {
+                peer.connected_since = Some(now);
+            } else {
+                peer.schedule_retry(now);
+            }
+        }
+    }
+}
+
+impl StaticPeer {
+    fn schedule_retry(&mut self, now: Instant) {
+        self.next_attempt = now + self.backoff;
+        self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let static_peers = StaticPeers::default();
+        let id = PeerId::random();
+        assert!(static_peers.insert(NodeRecord {
+            id,
+            addr: "127.0.0.1:30303".parse().unwrap(),
+        }));
+        let now = Instant::now();
+
+        assert_eq!(static_peers.due(now, |_| false).len(), 1);
+        // Already being dialed.
+        assert!(static_peers.due(now, |_| false).is_empty());
+
+        static_peers.dial_finished(id, false, now);
+        assert!(static_peers.due(now, |_| false).is_empty());
+        assert_eq!(static_peers.due(now + MIN_BACKOFF, |_| false).len(), 1);
+
+        static_peers.dial_finished(id, false, now + MIN_BACKOFF);
+        assert!(static_peers
+            .due(now + MIN_BACKOFF * 2, |_| false)
+            .is_empty());
+        assert_eq!(static_peers.due(now + MIN_BACKOFF * 3, |_| false).len(), 1);
+
+        // Stable session resets backoff.
+        let connected_at = now + MIN_BACKOFF * 3;
+        static_peers.dial_finished(id, true, connected_at);
+        assert!(static_peers.due(connected_at, |_| true).is_empty());
+        assert_eq!(
+            static_peers
+                .due(connected_at + STABLE_SESSION, |_| false)
+                .len(),
+            1
+        );
+    }
+}
@@ -7,7 +7,12 @@ use derive_more::Display;
 use educe::Educe;
 pub use ethereum_types::H512 as PeerId;
 use rlp::{DecoderError, Rlp, RlpStream};
-use std::{collections::HashMap, fmt::Debug, net::SocketAddr, str::FromStr};
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug},
+    net::SocketAddr,
+    str::FromStr,
+};
 
 /// Record that specifies information necessary to connect to RLPx node
 #[derive(Clone, Copy, Debug)]
@@ -24,10 +29,7 @@ impl FromStr for NodeRecord {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         const PREFIX: &str = "enode://";
 
-        let (prefix, data) = s.split_at(PREFIX.len());
-        if prefix != PREFIX {
-            return Err("Not an enode".into());
-        }
+        let data = s.strip_prefix(PREFIX).ok_or("Not an enode")?;
 
         let mut parts = data.split('@');
         let id = parts.next().ok_or("Failed to read remote ID")?.parse()?;
@@ -37,6 +39,12 @@ impl FromStr for NodeRecord {
     }
 }
 
+impl fmt::Display for NodeRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "enode://{:x}@{}", self.id, self.addr)
+    }
+}
+
 #[derive(Clone, Copy, Debug, Display, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct CapabilityName(pub ArrayString<[u8; 4]>);
 